@@ -1,24 +1,65 @@
-use std::collections::HashSet;
-use std::fs;
-use std::iter::FromIterator;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use tokio::fs;
 use tokio::join;
+use tokio::sync::mpsc;
 
+use crate::backoff::{backoff_delay, Backoff};
 use crate::client;
 use crate::error::{Error, ErrorKind, Result};
+use crate::output;
 use crate::reddit;
+use crate::session::Session;
 use crate::settings;
 
 const LISTING_LIMIT: u32 = 50;
+const INFO_LIMIT: usize = 100;
+#[cfg(not(test))]
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+#[cfg(test)]
+const RETRY_DELAY: Duration = Duration::from_millis(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+const RATE_LIMIT_PER_MINUTE: u32 = 60;
+const MAX_AUTO_CONCURRENCY: usize = 8;
+/// How many deletion results may wait to be collected before delete tasks
+/// wait for them to be.
+const RESULTS_BUFFER: usize = 64;
+/// How far down a subreddit's top posts of the day still counts as trending.
+const TRENDING_LIMIT: u32 = 25;
+const OVER_18_WARNING: &str = "your account's over_18 preference is off, so \
+    NSFW content may be skipped. Use `--set-over18` to enable it for this run";
+const MISSING_SR_DETAIL_WARNING: &str = "some items don't say whether you \
+    moderate their subreddit, so they won't be approved or removed. Scan \
+    with `--mod-action` to include it in the snapshot";
 
+/// The futures returned by `App` are `Send` and only rely on `join!` and
+/// `tokio::spawn`, so they make progress on both the basic and the threaded
+/// scheduler. Avoid blocking calls in them, since on the basic scheduler they
+/// would stall every in-flight request.
 #[async_trait]
 pub(crate) trait App: Send {
-    async fn regreddit(
+    async fn regreddit(&self, p: &RegredditParams<'_>) -> Result<RunReport>;
+    /// Collects everything `regreddit` would look at without deleting
+    /// anything. Pass the snapshot back through `RegredditParams::snapshot`
+    /// to delete from it later.
+    async fn scan(&self, p: &RegredditParams<'_>) -> Result<ScanResult>;
+    /// Deletes a post and submits it again, which resets its age and score.
+    async fn refresh(&self, p: &RefreshParams<'_>) -> Result<RefreshResult>;
+    /// Sets the preferences saved by `RegredditParams::backup_prefs`.
+    async fn restore_prefs(
         &self,
-        p: &RegredditParams<'_>,
-    ) -> Result<RegredditResult>;
+        p: &RestorePrefsParams<'_>,
+    ) -> Result<RestorePrefsResult>;
     async fn submit_link(
         &self,
         p: &SubmitLinkParams<'_>,
@@ -27,6 +68,10 @@ pub(crate) trait App: Send {
         &self,
         p: &SubmitSelfPostParams<'_>,
     ) -> Result<SubmitSelfPostResult>;
+    async fn submit_video(
+        &self,
+        p: &SubmitVideoParams<'_>,
+    ) -> Result<SubmitVideoResult>;
 }
 
 pub(crate) struct AppImpl {
@@ -37,6 +82,15 @@ pub(crate) struct Params {
     pub client: Box<dyn client::Client>,
 }
 
+// Every delete task holds the client and the session.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<client::ClientImpl>();
+    assert_send_sync::<Arc<Box<dyn client::Client>>>();
+    assert_send_sync::<Arc<Session>>();
+};
+
 impl AppImpl {
     pub(crate) fn new(p: Params) -> Self {
         AppImpl {
@@ -47,44 +101,237 @@ impl AppImpl {
 
 #[async_trait]
 impl App for AppImpl {
-    async fn regreddit(
-        &self,
-        p: &RegredditParams<'_>,
-    ) -> Result<RegredditResult> {
+    async fn regreddit(&self, p: &RegredditParams<'_>) -> Result<RunReport> {
+        if let (Some(min), Some(max)) = (p.score_min, p.score_max) {
+            if min > max {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the minimum score {} is greater than the maximum {}",
+                        min, max
+                    ),
+                ));
+            }
+        }
+
         log::info!("Nuking your Reddit...");
 
-        let res = self
+        let started_at = unix_now();
+        let auth_started = Instant::now();
+        let access_token = self
             .client
             .basic_auth(&client::BasicAuthParams {
                 credentials: &p.settings.credentials,
             })
-            .await?;
-        let access_token = res.access_token.clone();
-        let mut delete_comment_handles = Vec::new();
-        let mut delete_post_handles = Vec::new();
-        let whitelist = HashSet::from_iter(p.settings.whitelist.clone());
-        let (_, _) = join!(
-            self.delete_comments(
-                &access_token,
-                &mut delete_comment_handles,
-                &whitelist,
-            ),
-            self.delete_posts(
+            .await?
+            .access_token;
+        let latency = auth_started.elapsed();
+        let mut scan = match p.snapshot {
+            Some(snapshot) => {
+                if p.backup_prefs.is_some() {
+                    self.get_prefs(p, &access_token).await?;
+                }
+
+                log::info!("Deleting from the snapshot...");
+                ScanResult {
+                    snapshot: snapshot.clone(),
+                    warnings: Vec::new(),
+                }
+            }
+            None => self.take_snapshot(p, &access_token).await?,
+        };
+
+        if p.mod_action.is_some()
+            && scan
+                .snapshot
+                .comments
+                .iter()
+                .chain(&scan.snapshot.posts)
+                .any(|item| !item.has_sr_detail())
+        {
+            log::warn!("{}.", MISSING_SR_DETAIL_WARNING);
+            scan.warnings.push(MISSING_SR_DETAIL_WARNING.to_string());
+        }
+
+        let mut report = self
+            .delete_everything(
+                p,
+                scan.snapshot,
                 &access_token,
-                &mut delete_post_handles,
-                &whitelist,
-            ),
-        );
+                latency,
+                started_at,
+            )
+            .await?;
+
+        report.warnings = scan.warnings;
+
+        Ok(report)
+    }
+
+    async fn scan(&self, p: &RegredditParams<'_>) -> Result<ScanResult> {
+        log::info!("Scanning your Reddit...");
+
+        let access_token = self
+            .client
+            .basic_auth(&client::BasicAuthParams {
+                credentials: &p.settings.credentials,
+            })
+            .await?
+            .access_token;
+        self.take_snapshot(p, &access_token).await
+    }
 
-        for handle in delete_comment_handles {
-            let _ = handle.await;
+    async fn refresh(&self, p: &RefreshParams<'_>) -> Result<RefreshResult> {
+        if !p.id.starts_with("t3_") || !reddit::is_valid_fullname(p.id) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not the fullname of a post", p.id),
+            ));
         }
 
-        for handle in delete_post_handles {
-            let _ = handle.await;
+        log::info!("Authenticating with Reddit...");
+
+        let access_token = &self
+            .client
+            .basic_auth(&client::BasicAuthParams {
+                credentials: p.credentials,
+            })
+            .await?
+            .access_token;
+
+        log::info!("Getting {}...", p.id);
+
+        let ids = [p.id.to_string()];
+        let found = match self
+            .client
+            .get_info(&client::GetInfoParams {
+                access_token,
+                ids: &ids,
+            })
+            .await?
+            .response
+        {
+            reddit::Object::Listing { children, .. } => {
+                children.into_iter().next()
+            }
+            _ => None,
+        };
+        let backup = match (p.backup, &found) {
+            (Some(path), Some(found)) => Some((
+                path,
+                serde_json::to_vec_pretty(found)
+                    .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?,
+            )),
+            _ => None,
+        };
+        // Everything is checked before deleting, so a post that can't be
+        // submitted again is left alone.
+        let post = match found {
+            Some(reddit::Object::Link {
+                subreddit,
+                title,
+                is_self: true,
+                selftext,
+                ..
+            }) => reddit::Post::SelfPost {
+                subreddit,
+                title,
+                body: reddit::SelfPostBody::Text(selftext),
+            },
+            Some(reddit::Object::Link {
+                subreddit,
+                title,
+                url,
+                ..
+            }) => {
+                let url = url::Url::parse(&url)?;
+
+                if url
+                    .host_str()
+                    .map_or(false, |host| host.ends_with("redd.it"))
+                {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "posts with media hosted on Reddit can't be refreshed",
+                    ));
+                }
+
+                if !p.allow_repost {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "submitting a link post again may be flagged as a \
+                        repost; allow it to refresh anyway",
+                    ));
+                }
+
+                reddit::Post::Link {
+                    subreddit,
+                    title,
+                    url,
+                }
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("post {} was not found", p.id),
+                ))
+            }
+        };
+
+        if let Some((path, json)) = backup {
+            log::info!("Saving {} to {}...", p.id, path.display());
+            fs::write(path, json).await?;
         }
 
-        Ok(RegredditResult {})
+        log::info!("Deleting {}...", p.id);
+
+        let _res = self
+            .client
+            .delete_link(&client::DeleteLinkParams {
+                access_token,
+                id: p.id,
+            })
+            .await?;
+
+        log::info!("Submitting {} again...", p.id);
+
+        let res = self
+            .client
+            .submit(&client::SubmitParams {
+                access_token,
+                post,
+                send_replies: p.send_replies,
+            })
+            .await?;
+
+        Ok(RefreshResult { name: res.name })
+    }
+
+    async fn restore_prefs(
+        &self,
+        p: &RestorePrefsParams<'_>,
+    ) -> Result<RestorePrefsResult> {
+        log::info!("Authenticating with Reddit...");
+
+        let access_token = &self
+            .client
+            .basic_auth(&client::BasicAuthParams {
+                credentials: p.credentials,
+            })
+            .await?
+            .access_token;
+
+        log::info!("Restoring preferences...");
+
+        let _res = self
+            .client
+            .set_prefs(&client::SetPrefsParams {
+                access_token,
+                prefs: p.prefs,
+            })
+            .await?;
+
+        Ok(RestorePrefsResult {})
     }
 
     async fn submit_link(
@@ -102,21 +349,22 @@ impl App for AppImpl {
             .access_token;
 
         log::info!("Authentication successful.");
-        log::info!("Submitting link to r/{}...", p.subreddit);
 
-        let _ = self
-            .client
-            .submit(&client::SubmitParams {
+        let url = url::Url::parse(&p.url)?;
+        let submissions = self
+            .submit_to_subreddits(
                 access_token,
-                post: reddit::Post::Link {
-                    subreddit: p.subreddit.to_string(),
+                p.subreddits,
+                p.send_replies,
+                |subreddit| reddit::Post::Link {
+                    subreddit: subreddit.to_string(),
                     title: p.title.to_string(),
-                    url: url::Url::parse(&p.url)?,
+                    url: url.clone(),
                 },
-            })
-            .await?;
+            )
+            .await;
 
-        Ok(SubmitLinkResult {})
+        Ok(SubmitLinkResult { submissions })
     }
 
     async fn submit_self_post(
@@ -132,55 +380,26 @@ impl App for AppImpl {
             })
             .await?
             .access_token;
-        let submit_params: client::SubmitParams;
 
         log::info!("Authentication successful.");
-        log::info!("Submitting self-post to r/{}...", p.subreddit);
 
-        match (p.text, p.text_file, p.richtext_json, p.richtext_json_file) {
+        let body = match (
+            p.text,
+            p.text_file,
+            p.richtext_json,
+            p.richtext_json_file,
+        ) {
             (Some(t), None, None, None) => {
-                submit_params = client::SubmitParams {
-                    access_token,
-                    post: reddit::Post::SelfPost {
-                        subreddit: p.subreddit.to_string(),
-                        title: p.title.to_string(),
-                        body: reddit::SelfPostBody::Text(t.to_string()),
-                    },
-                }
+                reddit::SelfPostBody::Text(t.to_string())
             }
             (None, Some(f), None, None) => {
-                submit_params = client::SubmitParams {
-                    access_token,
-                    post: reddit::Post::SelfPost {
-                        subreddit: p.subreddit.to_string(),
-                        title: p.title.to_string(),
-                        body: reddit::SelfPostBody::Text(fs::read_to_string(
-                            f,
-                        )?),
-                    },
-                }
+                reddit::SelfPostBody::Text(read_text_file(f).await?)
             }
             (None, None, Some(r), None) => {
-                submit_params = client::SubmitParams {
-                    access_token,
-                    post: reddit::Post::SelfPost {
-                        subreddit: p.subreddit.to_string(),
-                        title: p.title.to_string(),
-                        body: reddit::SelfPostBody::RichtextJson(r.to_string()),
-                    },
-                }
+                reddit::SelfPostBody::RichtextJson(r.to_string())
             }
             (None, None, None, Some(f)) => {
-                submit_params = client::SubmitParams {
-                    access_token,
-                    post: reddit::Post::SelfPost {
-                        subreddit: p.subreddit.to_string(),
-                        title: p.title.to_string(),
-                        body: reddit::SelfPostBody::RichtextJson(
-                            fs::read_to_string(f)?,
-                        ),
-                    },
-                }
+                reddit::SelfPostBody::RichtextJson(read_text_file(f).await?)
             }
             _ => {
                 return Err(Error::new(
@@ -188,214 +407,2558 @@ impl App for AppImpl {
                     "only one input source is accepted",
                 ))
             }
+        };
+        let submissions = self
+            .submit_to_subreddits(
+                access_token,
+                p.subreddits,
+                p.send_replies,
+                |subreddit| reddit::Post::SelfPost {
+                    subreddit: subreddit.to_string(),
+                    title: p.title.to_string(),
+                    body: body.clone(),
+                },
+            )
+            .await;
+
+        Ok(SubmitSelfPostResult { submissions })
+    }
+
+    async fn submit_video(
+        &self,
+        p: &SubmitVideoParams<'_>,
+    ) -> Result<SubmitVideoResult> {
+        log::info!("Authenticating with Reddit...");
+
+        let access_token = &self
+            .client
+            .basic_auth(&client::BasicAuthParams {
+                credentials: p.credentials,
+            })
+            .await?
+            .access_token;
+
+        log::info!("Authentication successful.");
+
+        let submissions = self
+            .submit_to_subreddits(
+                access_token,
+                p.subreddits,
+                p.send_replies,
+                |subreddit| reddit::Post::Video {
+                    subreddit: subreddit.to_string(),
+                    title: p.title.to_string(),
+                    video_path: PathBuf::from(p.video),
+                    thumbnail_path: PathBuf::from(p.thumbnail),
+                },
+            )
+            .await;
+
+        Ok(SubmitVideoResult { submissions })
+    }
+}
+
+/// Reads a file to submit as is. Reddit only takes UTF-8, so a file in any
+/// other encoding is rejected instead of being mangled.
+async fn read_text_file(path: &str) -> Result<String> {
+    fs::read_to_string(path).await.map_err(|err| {
+        if err.kind() == io::ErrorKind::InvalidData {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not valid UTF-8", path),
+            )
+        } else {
+            err.into()
+        }
+    })
+}
+
+/// A set of subreddit names. Reddit treats subreddit names
+/// case-insensitively, so they are matched that way unless `strict` is set.
+struct SubredditSet {
+    names: HashSet<String>,
+    strict: bool,
+}
+
+impl SubredditSet {
+    fn new(names: HashSet<String>, strict: bool) -> Self {
+        let names = if strict {
+            names
+        } else {
+            names.iter().map(|name| name.to_lowercase()).collect()
+        };
+
+        SubredditSet { names, strict }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        if self.strict {
+            self.names.contains(name)
+        } else {
+            self.names.contains(&name.to_lowercase())
         }
+    }
+}
 
-        let _ = self.client.submit(&submit_params).await?;
+#[derive(Clone, Copy, Debug)]
+enum ListingKind {
+    Comments,
+    Posts,
+    Saved,
+}
 
-        Ok(SubmitSelfPostResult {})
+impl ListingKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ListingKind::Comments => "comments",
+            ListingKind::Posts => "posts",
+            ListingKind::Saved => "saved items",
+        }
     }
 }
 
 impl AppImpl {
-    async fn delete_comments(
+    /// Gets the account's preferences, backing them up first if asked to.
+    async fn get_prefs(
         &self,
+        p: &RegredditParams<'_>,
         access_token: &str,
-        handles: &mut Vec<tokio::task::JoinHandle<()>>,
-        whitelist: &HashSet<String>,
-    ) -> Result<()> {
-        let limit = Some(LISTING_LIMIT);
-        let mut after: Option<String> = None;
+    ) -> Result<reddit::Prefs> {
+        let prefs = self
+            .client
+            .get_prefs(&client::GetPrefsParams { access_token })
+            .await?
+            .prefs;
 
-        loop {
-            log::info!("Getting next page of comments...");
+        if let Some(path) = p.backup_prefs {
+            log::info!("Backing up preferences to {}...", path.display());
 
-            if let reddit::Object::Listing { children, .. } = self
-                .client
-                .get_comments(&client::GetCommentsParams {
-                    access_token: &access_token,
-                    username: &"trustyhardware",
-                    listing_control: &reddit::ListingControl {
-                        after,
-                        before: None,
-                        count: None,
-                        limit,
-                        show: None,
-                    },
-                })
-                .await?
-                .response
-            {
-                for child in &children {
-                    if let reddit::Object::Comment {
-                        name, subreddit, ..
-                    } = child
-                    {
-                        if whitelist.contains(subreddit) {
-                            log::info!(
-                                "Comment is in whitelisted subreddit. \
-                                Skipping..."
-                            );
-                            continue;
-                        }
-
-                        let access_token = access_token.to_owned();
-                        let client = self.client.clone();
-                        let name = name.clone();
-
-                        handles.push(tokio::spawn(async move {
-                            match client
-                                .delete_link(&client::DeleteLinkParams {
-                                    access_token: &access_token,
-                                    id: &name,
-                                })
-                                .await
-                            {
-                                Ok(_res) => {
-                                    log::info!("Deleted comment {}.", name);
-                                }
-                                Err(err) => log::warn!(
-                                    "Failed to delete {}: {}.",
-                                    name,
-                                    err
-                                ),
-                            }
-                        }));
-                    } else {
-                        log::error!("Got unexpected object. Expected Link.");
-                        continue;
-                    }
-                }
+            let json = serde_json::to_vec_pretty(&prefs)
+                .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
 
-                if children.len() < LISTING_LIMIT as usize {
-                    break;
-                }
+            fs::write(path, json).await?;
+        }
 
-                if let Some(reddit::Object::Link { name, .. }) = children.last()
-                {
-                    after = Some(name.clone());
-                } else {
-                    break;
-                }
-            } else {
-                log::error!("Got unexpected object. Expected Listing.");
-                break;
-            }
+        Ok(prefs)
+    }
+
+    /// Collects both of the user's listings, with the over_18 preference
+    /// enabled for the duration if asked to.
+    async fn take_snapshot(
+        &self,
+        p: &RegredditParams<'_>,
+        access_token: &str,
+    ) -> Result<ScanResult> {
+        let prefs = self.get_prefs(p, access_token).await?;
+        let restore_over_18 = !prefs.over_18 && p.set_over_18;
+        let mut warnings = Vec::new();
+
+        if restore_over_18 {
+            log::info!("Temporarily enabling the over_18 preference...");
+            self.set_over_18(access_token, true).await?;
+        } else if !prefs.over_18 {
+            log::warn!("{}.", OVER_18_WARNING);
+            warnings.push(OVER_18_WARNING.to_string());
         }
 
-        Ok(())
+        let (comments, posts) = join!(
+            self.collect_listing(ListingKind::Comments, access_token, p),
+            self.collect_listing(ListingKind::Posts, access_token, p),
+        );
+        let saved = if p.unsave {
+            self.collect_listing(ListingKind::Saved, access_token, p)
+                .await
+        } else {
+            Ok(Vec::new())
+        };
+        let res = comments.and_then(|comments| {
+            Ok(ScanResult {
+                snapshot: Snapshot {
+                    comments,
+                    posts: posts?,
+                    saved: saved?,
+                },
+                warnings,
+            })
+        });
+
+        if restore_over_18 {
+            log::info!("Restoring the over_18 preference...");
+            self.set_over_18(access_token, false).await?;
+        }
+
+        res
     }
 
-    async fn delete_posts(
+    /// Deletes everything not filtered out. `latency` is how long a typical
+    /// request takes, which the automatic concurrency is derived from.
+    async fn delete_everything(
         &self,
+        p: &RegredditParams<'_>,
+        snapshot: Snapshot,
         access_token: &str,
-        handles: &mut Vec<tokio::task::JoinHandle<()>>,
-        whitelist: &HashSet<String>,
-    ) -> Result<()> {
-        let limit = Some(LISTING_LIMIT);
-        let mut after: Option<String> = None;
+        latency: Duration,
+        started_at: f64,
+    ) -> Result<RunReport> {
+        let (comment_whitelist, post_whitelist) = if p.ignore_whitelist {
+            log::warn!("Ignoring the whitelist. Everything will be deleted.");
+            (HashSet::new(), HashSet::new())
+        } else {
+            (p.settings.comment_whitelist(), p.settings.post_whitelist())
+        };
+        let comment_whitelist =
+            SubredditSet::new(comment_whitelist, p.strict_subreddit_match);
+        let post_whitelist =
+            SubredditSet::new(post_whitelist, p.strict_subreddit_match);
+        let Snapshot {
+            comments,
+            posts,
+            saved,
+        } = snapshot;
+        let mut report = RunReport::new(
+            started_at,
+            AgeRange::of(&comments),
+            AgeRange::of(&posts),
+        );
+        let skip_reason = |item: &reddit::Object| -> Option<SkipReason> {
+            match item.name() {
+                Some(name) if reddit::is_valid_fullname(name) => {}
+                Some(name) => {
+                    log::warn!(
+                        "Skipping {} with malformed name {:?}.",
+                        item.kind_name(),
+                        name
+                    );
+                    return Some(SkipReason::Malformed);
+                }
+                None => {
+                    log::error!(
+                        "Got unexpected object. Expected Comment or Link."
+                    );
+                    return Some(SkipReason::Malformed);
+                }
+            }
 
-        loop {
-            log::info!("Getting next page of posts...");
+            let whitelist = match item {
+                reddit::Object::Comment { .. } => &comment_whitelist,
+                _ => &post_whitelist,
+            };
 
-            if let reddit::Object::Listing { children, .. } = self
-                .client
-                .get_posts(&client::GetPostsParams {
-                    access_token: &access_token,
-                    username: &"trustyhardware",
-                    listing_control: &reddit::ListingControl {
-                        after,
-                        before: None,
-                        count: None,
-                        limit,
-                        show: None,
-                    },
-                })
-                .await?
-                .response
-            {
-                for post in &children {
-                    if let reddit::Object::Link {
-                        name, subreddit, ..
-                    } = post
-                    {
-                        if whitelist.contains(subreddit) {
-                            log::info!(
-                                "Post is in whitelisted subreddit. Skipping...",
-                            );
-                            continue;
-                        }
-
-                        let access_token = access_token.to_owned();
-                        let client = self.client.clone();
-                        let name = name.clone();
-
-                        handles.push(tokio::spawn(async move {
-                            match client
-                                .delete_link(&client::DeleteLinkParams {
-                                    access_token: &access_token,
-                                    id: &name,
-                                })
-                                .await
-                            {
-                                Ok(_res) => {
-                                    log::info!("Deleted post {}.", name);
-                                }
-                                Err(err) => log::warn!(
-                                    "Failed to delete {}: {}.",
-                                    name,
-                                    err
-                                ),
-                            }
-                        }));
-                    } else {
-                        log::error!("Got unexpected object. Expected Link.");
-                        continue;
-                    }
+            match item.subreddit() {
+                Some(subreddit) if whitelist.contains(subreddit) => {
+                    log::info!(
+                        "{} {} is in whitelisted subreddit. Skipping...",
+                        item.kind_name(),
+                        item.name().unwrap_or_default(),
+                    );
+                    return Some(SkipReason::Whitelisted);
                 }
+                _ => {}
+            }
 
-                if children.len() < LISTING_LIMIT as usize {
-                    break;
+            if p.only_inaccessible && !item.in_inaccessible_subreddit() {
+                return Some(SkipReason::Accessible);
+            }
+
+            if let (Some(depth), Some(top_level)) =
+                (p.comment_depth, item.is_top_level_comment())
+            {
+                if top_level != (depth == CommentDepth::TopLevel) {
+                    return Some(SkipReason::CommentDepth);
                 }
+            }
 
-                if let Some(reddit::Object::Link { name, .. }) = children.last()
-                {
-                    after = Some(name.clone());
-                } else {
-                    break;
+            if let (Some(after), Some(created)) =
+                (p.created_after, item.created_utc())
+            {
+                if created <= after {
+                    return Some(SkipReason::BeforeLastRun);
                 }
-            } else {
-                log::error!("Got unexpected object. Expected Listing.");
-                break;
+            }
+
+            let score = item.score().unwrap_or_default();
+
+            if !p.score_min.map_or(true, |min| score >= min)
+                || !p.score_max.map_or(true, |max| score <= max)
+            {
+                return Some(SkipReason::Score);
+            }
+
+            None
+        };
+        let mut items = Vec::new();
+
+        for item in comments.into_iter().chain(posts) {
+            match skip_reason(&item) {
+                Some(reason) => report.skip(&item, reason),
+                None => items.push(item),
             }
         }
 
-        Ok(())
-    }
-}
+        if p.keep_if_trending {
+            let trending = self.get_trending(p, access_token, &items).await?;
 
-pub(crate) struct SubmitLinkParams<'a> {
-    pub credentials: &'a settings::Credentials,
-    pub subreddit: &'a str,
-    pub title: &'a str,
-    pub url: &'a str,
-}
+            items.retain(|item| match item.name() {
+                Some(name) if trending.contains(name) => {
+                    log::info!("Post {} is trending. Skipping...", name);
+                    report.skip(item, SkipReason::Trending);
+                    false
+                }
+                _ => true,
+            });
+        }
 
-pub(crate) struct SubmitLinkResult {}
+        let mut rng = match p.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
 
-pub(crate) struct SubmitSelfPostParams<'a> {
-    pub credentials: &'a settings::Credentials,
-    pub subreddit: &'a str,
-    pub title: &'a str,
-    pub text: Option<&'a str>,
-    pub text_file: Option<&'a str>,
-    pub richtext_json: Option<&'a str>,
-    pub richtext_json_file: Option<&'a str>,
-}
+        if let Some(sample) = p.sample {
+            items.shuffle(&mut rng);
 
-pub(crate) struct SubmitSelfPostResult {}
+            if sample < items.len() {
+                for item in items.drain(sample..) {
+                    report.skip(&item, SkipReason::NotSampled);
+                }
+            }
 
-pub(crate) struct RegredditParams<'a> {
-    pub settings: &'a settings::Settings,
-}
+            log::info!("Sampled {} items to delete.", items.len());
+        }
+
+        if p.randomize_order {
+            items.shuffle(&mut rng);
+        }
 
-pub(crate) struct RegredditResult {}
+        if let Some(confirm) = p.confirm {
+            if !confirm(&items) {
+                log::info!("Deletion cancelled.");
+
+                report.cancelled = true;
+                report.finished_at = unix_now();
+
+                return Ok(report);
+            }
+        }
+
+        let total = items.len();
+        let workers = match p.concurrency {
+            Concurrency::Unlimited => total,
+            Concurrency::Auto => {
+                let workers = auto_concurrency(latency);
+
+                log::info!("Deleting up to {} items at a time.", workers);
+                workers
+            }
+            Concurrency::Fixed(workers) => workers,
+        };
+        let session = Arc::new(Session::new(
+            self.client.clone(),
+            p.settings.credentials.clone(),
+            access_token.to_string(),
+            p.max_auth_failures,
+            Backoff::new(RETRY_DELAY, MAX_RETRY_DELAY),
+        ));
+        delete_concurrently(
+            &self.client,
+            &session,
+            items,
+            workers,
+            p.mod_action,
+            p.delete_retries,
+            |deletion| {
+                report.record(deletion);
+                async {}
+            },
+        )
+        .await;
+
+        if report.deletions.len() < total {
+            log::error!(
+                "{} items were never deleted.",
+                total - report.deletions.len()
+            );
+        }
+
+        session.check()?;
+
+        report.unsaved = self.unsave_everything(p, saved, &session).await;
+
+        session.check()?;
+
+        report.finished_at = unix_now();
+
+        Ok(report)
+    }
+
+    /// Looks up the top posts of the day in each subreddit the user posted
+    /// one of `items` in, and returns the fullnames of those that are the
+    /// user's.
+    async fn get_trending(
+        &self,
+        p: &RegredditParams<'_>,
+        access_token: &str,
+        items: &[reddit::Object],
+    ) -> Result<HashSet<String>> {
+        let posts: HashSet<&str> = items
+            .iter()
+            .filter(|item| matches!(item, reddit::Object::Link { .. }))
+            .filter_map(|item| item.name())
+            .collect();
+        let subreddits: HashSet<&str> = items
+            .iter()
+            .filter(|item| matches!(item, reddit::Object::Link { .. }))
+            .filter_map(|item| item.subreddit())
+            .collect();
+        let listing_control = reddit::ListingControl {
+            after: None,
+            before: None,
+            count: None,
+            limit: Some(TRENDING_LIMIT),
+            show: None,
+            sr_detail: None,
+            extra: HashMap::new(),
+        };
+        let mut trending = HashSet::new();
+
+        for subreddit in subreddits {
+            log::info!("Getting top posts of r/{}...", subreddit);
+
+            let params = client::GetSubredditTopParams {
+                access_token,
+                subreddit,
+                time: "day",
+                listing_control: &listing_control,
+            };
+            let top = retry(p.listing_retries, || {
+                self.client.get_subreddit_top(&params)
+            })
+            .await?
+            .response;
+
+            match top {
+                reddit::Object::Listing { children, .. } => trending.extend(
+                    children
+                        .iter()
+                        .filter_map(|child| child.name())
+                        .filter(|name| posts.contains(name))
+                        .map(|name| name.to_string()),
+                ),
+                _ => log::error!("Got unexpected object. Expected Listing."),
+            }
+        }
+
+        Ok(trending)
+    }
+
+    /// Unsaves saved items, only those by other users if asked to. Returns how
+    /// many were unsaved.
+    async fn unsave_everything(
+        &self,
+        p: &RegredditParams<'_>,
+        saved: Vec<reddit::Object>,
+        session: &Session,
+    ) -> usize {
+        let username = &p.settings.credentials.username;
+        let mut unsaved = 0;
+
+        for item in saved {
+            let name = match item.name() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if p.unsave_others_only
+                && item.author().map_or(false, |author| {
+                    author.eq_ignore_ascii_case(username)
+                })
+            {
+                log::info!(
+                    "Keeping your own saved {} {}.",
+                    item.kind_name(),
+                    name
+                );
+                continue;
+            }
+
+            let res = retry(p.delete_retries, || {
+                unsave(&**self.client, session, name)
+            })
+            .await;
+
+            match res {
+                Ok(()) => {
+                    log::info!("Unsaved {}.", name);
+                    unsaved += 1;
+                }
+                Err(err) => log::warn!("Failed to unsave {}: {}.", name, err),
+            }
+        }
+
+        unsaved
+    }
+
+    /// Submits a post to each subreddit in turn. A failed submission does not
+    /// stop the rest.
+    async fn submit_to_subreddits<F>(
+        &self,
+        access_token: &str,
+        subreddits: &[&str],
+        send_replies: bool,
+        mut post: F,
+    ) -> Vec<Submission>
+    where
+        F: FnMut(&str) -> reddit::Post,
+    {
+        let mut submissions = Vec::new();
+
+        for &subreddit in subreddits {
+            log::info!("Submitting to r/{}...", subreddit);
+
+            let res = self
+                .client
+                .submit(&client::SubmitParams {
+                    access_token,
+                    post: post(subreddit),
+                    send_replies,
+                })
+                .await;
+
+            let submission = match res {
+                Ok(res) => Submission {
+                    subreddit: subreddit.to_string(),
+                    name: res.name,
+                    error: None,
+                },
+                Err(err) => {
+                    log::warn!("Failed to submit to r/{}: {}.", subreddit, err);
+
+                    Submission {
+                        subreddit: subreddit.to_string(),
+                        name: None,
+                        error: Some(err.to_string()),
+                    }
+                }
+            };
+
+            submissions.push(submission);
+        }
+
+        submissions
+    }
+
+    async fn set_over_18(
+        &self,
+        access_token: &str,
+        over_18: bool,
+    ) -> Result<()> {
+        let _ = self
+            .client
+            .set_prefs(&client::SetPrefsParams {
+                access_token,
+                prefs: &reddit::Prefs {
+                    over_18,
+                    other: HashMap::new(),
+                },
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Pages through one of the user's listings and collects every item in
+    /// it. Reddit only serves the most recent 1000 items of a listing, so this
+    /// stays small.
+    async fn collect_listing(
+        &self,
+        kind: ListingKind,
+        access_token: &str,
+        p: &RegredditParams<'_>,
+    ) -> Result<Vec<reddit::Object>> {
+        let username = &p.settings.credentials.username;
+        let mut items = Vec::new();
+        let mut after: Option<String> = None;
+        let mut more_ids = Vec::new();
+        let mut pages = 0;
+
+        loop {
+            if p.max_listing_pages == Some(pages) {
+                log::warn!(
+                    "Stopped scanning {} after {} pages.",
+                    kind.as_str(),
+                    pages
+                );
+                break;
+            }
+
+            pages += 1;
+            log::info!("Getting next page of {}...", kind.as_str());
+
+            let listing_control = reddit::ListingControl {
+                after,
+                before: None,
+                count: None,
+                limit: Some(LISTING_LIMIT),
+                show: None,
+                extra: p.listing_params.clone(),
+                sr_detail: if p.only_inaccessible || p.mod_action.is_some() {
+                    Some(true)
+                } else {
+                    None
+                },
+            };
+            let page = retry(p.listing_retries, || {
+                self.get_listing_page(
+                    kind,
+                    access_token,
+                    username,
+                    &listing_control,
+                )
+            })
+            .await?;
+            // Reddit says where the next page starts, even when this one
+            // ends in a "load more" stub without a name.
+            let children = match page {
+                reddit::Object::Listing {
+                    children,
+                    after: next,
+                    ..
+                } => {
+                    after = next;
+                    children
+                }
+                _ => {
+                    log::error!("Got unexpected object. Expected Listing.");
+                    break;
+                }
+            };
+
+            for child in children {
+                match child {
+                    reddit::Object::More { children: ids } => {
+                        more_ids.extend(ids)
+                    }
+                    child => items.push(child),
+                }
+            }
+
+            if after.is_none() {
+                break;
+            }
+        }
+
+        if !more_ids.is_empty() {
+            if p.thorough {
+                items.extend(self.get_info(access_token, &more_ids, p).await?);
+            } else {
+                log::warn!(
+                    "Skipped {} {} behind \"load more\" stubs. Use \
+                    `--thorough` to include them.",
+                    more_ids.len(),
+                    kind.as_str(),
+                );
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Looks up comments by the ids found in "load more" stubs.
+    async fn get_info(
+        &self,
+        access_token: &str,
+        ids: &[String],
+        p: &RegredditParams<'_>,
+    ) -> Result<Vec<reddit::Object>> {
+        let fullnames: Vec<String> = ids
+            .iter()
+            .map(|id| {
+                if id.contains('_') {
+                    id.clone()
+                } else {
+                    format!("t1_{}", id)
+                }
+            })
+            .collect();
+        let mut items = Vec::new();
+
+        for chunk in fullnames.chunks(INFO_LIMIT) {
+            log::info!("Getting {} comments by id...", chunk.len());
+
+            let params = client::GetInfoParams {
+                access_token,
+                ids: chunk,
+            };
+
+            match retry(p.listing_retries, || self.client.get_info(&params))
+                .await?
+                .response
+            {
+                reddit::Object::Listing { children, .. } => {
+                    items.extend(children)
+                }
+                _ => log::error!("Got unexpected object. Expected Listing."),
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn get_listing_page(
+        &self,
+        kind: ListingKind,
+        access_token: &str,
+        username: &str,
+        listing_control: &reddit::ListingControl,
+    ) -> Result<reddit::Object> {
+        match kind {
+            ListingKind::Comments => Ok(self
+                .client
+                .get_comments(&client::GetCommentsParams {
+                    access_token,
+                    username,
+                    listing_control,
+                })
+                .await?
+                .response),
+            ListingKind::Posts => Ok(self
+                .client
+                .get_posts(&client::GetPostsParams {
+                    access_token,
+                    username,
+                    listing_control,
+                })
+                .await?
+                .response),
+            ListingKind::Saved => Ok(self
+                .client
+                .get_saved(&client::GetSavedParams {
+                    access_token,
+                    username,
+                    listing_control,
+                })
+                .await?
+                .response),
+        }
+    }
+}
+
+/// The number of deletes that keeps Reddit's rate limit busy without going
+/// over it, given how long a request takes.
+fn auto_concurrency(latency: Duration) -> usize {
+    let per_second = f64::from(RATE_LIMIT_PER_MINUTE) / 60.0;
+    let permits = (per_second * latency.as_secs_f64()).ceil() as usize;
+
+    permits.clamp(1, MAX_AUTO_CONCURRENCY)
+}
+
+/// The current time in seconds since the Unix epoch.
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// Deletes `items` with up to `workers` deletes in flight, handing each
+/// result to `record` as it comes in.
+async fn delete_concurrently<F, Fut>(
+    client: &Arc<Box<dyn client::Client>>,
+    session: &Arc<Session>,
+    items: Vec<reddit::Object>,
+    workers: usize,
+    mod_action: Option<ModAction>,
+    delete_retries: u32,
+    mut record: F,
+) where
+    F: FnMut(Deletion) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let total = items.len();
+    // A fixed number of workers take items off the queue one at a time, so
+    // only as many deletes as there are workers are in flight. Results go
+    // through a bounded channel, so workers wait when the results aren't
+    // being taken fast enough.
+    let queue = Arc::new(Mutex::new(VecDeque::from(items)));
+    let (results, mut results_rx) = mpsc::channel(RESULTS_BUFFER);
+
+    for _ in 0..workers.min(total) {
+        let queue = queue.clone();
+        let client = client.clone();
+        let session = session.clone();
+        let mut results = results.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let item = queue.lock().unwrap().pop_front();
+                let item = match item {
+                    Some(item) => item,
+                    None => break,
+                };
+                let deletion = delete_item(
+                    &**client,
+                    &session,
+                    &item,
+                    mod_action,
+                    delete_retries,
+                )
+                .await;
+
+                if results.send(deletion).await.is_err() {
+                    log::error!("Dropped a deletion result.");
+                    break;
+                }
+            }
+        });
+    }
+
+    // Only the workers' senders are left, so the channel closes once the last
+    // worker is done.
+    drop(results);
+
+    while let Some(deletion) = results_rx.recv().await {
+        record(deletion).await;
+    }
+}
+
+/// Takes `mod_action`, if the user moderates the item's subreddit, and
+/// deletes the item.
+async fn delete_item(
+    client: &dyn client::Client,
+    session: &Session,
+    item: &reddit::Object,
+    mod_action: Option<ModAction>,
+    delete_retries: u32,
+) -> Deletion {
+    let name = item.name().unwrap_or_default().to_string();
+    let subreddit = item.subreddit().unwrap_or_default().to_string();
+    let mod_action = mod_action.filter(|action| {
+        let moderated = item.user_is_moderator();
+
+        if !moderated {
+            log::debug!(
+                "Not a moderator of r/{}. Won't {} {}.",
+                subreddit,
+                action.as_str(),
+                name
+            );
+        }
+
+        moderated
+    });
+
+    if let Some(action) = mod_action {
+        let res =
+            retry(delete_retries, || moderate(client, session, action, &name))
+                .await;
+
+        if let Err(err) = res {
+            log::warn!("Failed to {} {}: {}.", action.as_str(), name, err);
+        }
+    }
+
+    let res = retry(delete_retries, || delete(client, session, &name)).await;
+    let error = match res {
+        Ok(_res) => {
+            log::info!("Deleted {} ({}).", output::describe(item), name);
+            None
+        }
+        Err(err) => {
+            log::warn!("Failed to delete {}: {}.", name, err);
+            Some(err.to_string())
+        }
+    };
+
+    Deletion {
+        kind: item.kind_name(),
+        name,
+        subreddit,
+        deleted: error.is_none(),
+        error,
+    }
+}
+
+/// Deletes a thing.
+async fn delete(
+    client: &dyn client::Client,
+    session: &Session,
+    id: &str,
+) -> Result<()> {
+    with_session(session, |access_token| async move {
+        client
+            .delete_link(&client::DeleteLinkParams {
+                access_token: &access_token,
+                id,
+            })
+            .await
+            .map(|_res| ())
+    })
+    .await
+}
+
+/// Unsaves a thing.
+async fn unsave(
+    client: &dyn client::Client,
+    session: &Session,
+    id: &str,
+) -> Result<()> {
+    with_session(session, |access_token| async move {
+        client
+            .unsave(&client::UnsaveParams {
+                access_token: &access_token,
+                id,
+            })
+            .await
+            .map(|_res| ())
+    })
+    .await
+}
+
+/// Approves or removes a thing in a subreddit the user moderates.
+async fn moderate(
+    client: &dyn client::Client,
+    session: &Session,
+    action: ModAction,
+    id: &str,
+) -> Result<()> {
+    with_session(session, |access_token| async move {
+        match action {
+            ModAction::Approve => client
+                .approve(&client::ApproveParams {
+                    access_token: &access_token,
+                    id,
+                })
+                .await
+                .map(|_res| ()),
+            ModAction::Remove => client
+                .remove(&client::RemoveParams {
+                    access_token: &access_token,
+                    id,
+                })
+                .await
+                .map(|_res| ()),
+        }
+    })
+    .await
+}
+
+/// Calls `f` with the session's access token, authenticating again once if
+/// the token is rejected.
+async fn with_session<T, F, Fut>(session: &Session, mut f: F) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let access_token = session.access_token().await?;
+
+    match f(access_token.clone()).await {
+        Err(ref err) if err.kind() == ErrorKind::Authentication => {
+            session.reauthenticate(&access_token).await?;
+            f(session.access_token().await?).await
+        }
+        res => res,
+    }
+}
+
+/// Calls `f` until it succeeds or has been retried `retries` times, backing
+/// off in between.
+async fn retry<T, F, Fut>(retries: u32, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match f().await {
+            Ok(res) => return Ok(res),
+            // Authentication failures are handled by the session.
+            Err(err)
+                if attempt < retries
+                    && err.kind() != ErrorKind::Authentication =>
+            {
+                attempt += 1;
+                log::warn!(
+                    "Request failed: {}. Retrying ({}/{})...",
+                    err,
+                    attempt,
+                    retries
+                );
+                tokio::time::delay_for(backoff_delay(
+                    attempt - 1,
+                    RETRY_DELAY,
+                    MAX_RETRY_DELAY,
+                    true,
+                ))
+                .await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct RefreshParams<'a> {
+    pub credentials: &'a settings::Credentials,
+    /// The fullname of the post, e.g. `t3_abc123`.
+    pub id: &'a str,
+    /// Refresh link posts too, which Reddit may flag as reposts.
+    pub allow_repost: bool,
+    /// Whether replies go to the user's inbox.
+    pub send_replies: bool,
+    /// Save the post here before deleting it, in case submitting it again
+    /// fails.
+    pub backup: Option<&'a Path>,
+}
+
+pub(crate) struct RefreshResult {
+    /// The fullname of the new post, when Reddit tells us.
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct RestorePrefsParams<'a> {
+    pub credentials: &'a settings::Credentials,
+    pub prefs: &'a reddit::Prefs,
+}
+
+pub(crate) struct RestorePrefsResult {}
+
+#[derive(Clone, Copy)]
+pub(crate) struct SubmitLinkParams<'a> {
+    pub credentials: &'a settings::Credentials,
+    pub subreddits: &'a [&'a str],
+    pub title: &'a str,
+    pub url: &'a str,
+    /// Whether replies go to the user's inbox.
+    pub send_replies: bool,
+}
+
+pub(crate) struct SubmitLinkResult {
+    pub submissions: Vec<Submission>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct SubmitSelfPostParams<'a> {
+    pub credentials: &'a settings::Credentials,
+    pub subreddits: &'a [&'a str],
+    pub title: &'a str,
+    pub text: Option<&'a str>,
+    pub text_file: Option<&'a str>,
+    pub richtext_json: Option<&'a str>,
+    pub richtext_json_file: Option<&'a str>,
+    /// Whether replies go to the user's inbox.
+    pub send_replies: bool,
+}
+
+pub(crate) struct SubmitSelfPostResult {
+    pub submissions: Vec<Submission>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct SubmitVideoParams<'a> {
+    pub credentials: &'a settings::Credentials,
+    pub subreddits: &'a [&'a str],
+    pub title: &'a str,
+    pub video: &'a str,
+    pub thumbnail: &'a str,
+    /// Whether replies go to the user's inbox.
+    pub send_replies: bool,
+}
+
+pub(crate) struct SubmitVideoResult {
+    pub submissions: Vec<Submission>,
+}
+
+/// The outcome of submitting a post to a single subreddit.
+pub(crate) struct Submission {
+    pub subreddit: String,
+    /// The fullname of the new post, when Reddit tells us.
+    pub name: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone)]
+pub(crate) struct RegredditParams<'a> {
+    pub settings: &'a settings::Settings,
+    pub listing_retries: u32,
+    pub delete_retries: u32,
+    pub sample: Option<usize>,
+    pub seed: Option<u64>,
+    pub set_over_18: bool,
+    pub ignore_whitelist: bool,
+    pub strict_subreddit_match: bool,
+    /// Only delete items in banned, private or quarantined subreddits.
+    pub only_inaccessible: bool,
+    /// Delete items in random order instead of newest first. The whole list
+    /// is shuffled at once, after filtering, so items from different listing
+    /// pages are mixed.
+    pub randomize_order: bool,
+    /// Keep posts that are among the top posts of the day in their
+    /// subreddit. Costs a request per subreddit.
+    pub keep_if_trending: bool,
+    /// Only delete top-level comments, or only replies. Posts are
+    /// unaffected.
+    pub comment_depth: Option<CommentDepth>,
+    /// Only delete items created after this, in seconds since the Unix
+    /// epoch.
+    pub created_after: Option<f64>,
+    /// Only delete items with at least this score.
+    pub score_min: Option<i64>,
+    /// Only delete items with at most this score.
+    pub score_max: Option<i64>,
+    pub max_listing_pages: Option<u32>,
+    /// Extra query parameters sent with every listing request.
+    pub listing_params: HashMap<String, String>,
+    pub thorough: bool,
+    pub concurrency: Concurrency,
+    /// How many consecutive failed authentications abort the run.
+    pub max_auth_failures: u32,
+    /// In subreddits the user moderates, approve or remove each item before
+    /// deleting it.
+    pub mod_action: Option<ModAction>,
+    /// Save the account's preferences here before changing any.
+    pub backup_prefs: Option<&'a Path>,
+    /// Also unsave every saved item.
+    pub unsave: bool,
+    /// Only unsave items by other users.
+    pub unsave_others_only: bool,
+    /// Delete from a snapshot taken by `scan` instead of the live listings.
+    pub snapshot: Option<&'a Snapshot>,
+    /// Called with everything about to be deleted. Nothing is deleted unless
+    /// it returns `true`.
+    pub confirm: Option<&'a (dyn Fn(&[reddit::Object]) -> bool + Sync)>,
+}
+
+/// How many deletes may be in flight at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Concurrency {
+    Unlimited,
+    /// Derived from Reddit's rate limit and the observed request latency.
+    Auto,
+    Fixed(usize),
+}
+
+impl FromStr for Concurrency {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "auto" | "0" => Ok(Concurrency::Auto),
+            "unlimited" => Ok(Concurrency::Unlimited),
+            _ => match s.parse() {
+                Ok(permits) => Ok(Concurrency::Fixed(permits)),
+                Err(err) => Err(format!("invalid concurrency {}: {}", s, err)),
+            },
+        }
+    }
+}
+
+/// Which comments to delete, by whether they reply to a post or to another
+/// comment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum CommentDepth {
+    TopLevel,
+    Replies,
+}
+
+impl CommentDepth {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            CommentDepth::TopLevel => "top-level",
+            CommentDepth::Replies => "replies",
+        }
+    }
+}
+
+/// A moderator action to take on an item before deleting it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ModAction {
+    Approve,
+    Remove,
+}
+
+impl ModAction {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ModAction::Approve => "approve",
+            ModAction::Remove => "remove",
+        }
+    }
+}
+
+impl FromStr for ModAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "approve" => Ok(ModAction::Approve),
+            "remove" => Ok(ModAction::Remove),
+            _ => Err(format!("unknown moderator action {}", s)),
+        }
+    }
+}
+
+pub(crate) struct ScanResult {
+    pub snapshot: Snapshot,
+    /// Why the snapshot may be missing items.
+    pub warnings: Vec<String>,
+}
+
+/// Every comment and post of a user at the time of a scan.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Snapshot {
+    pub comments: Vec<reddit::Object>,
+    pub posts: Vec<reddit::Object>,
+    /// Only collected when unsaving.
+    #[serde(default)]
+    pub saved: Vec<reddit::Object>,
+}
+
+/// Everything a run did and didn't do. Every output format and notification
+/// is written from this.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunReport {
+    /// When the run started and finished, in seconds since the Unix epoch.
+    pub started_at: f64,
+    pub finished_at: f64,
+    /// Whether `confirm` declined, so nothing was deleted.
+    pub cancelled: bool,
+    pub comments: Counts,
+    pub posts: Counts,
+    /// The counts of comments and posts together, by subreddit.
+    pub subreddits: BTreeMap<String, Counts>,
+    /// How many items were skipped for each reason.
+    pub skipped: BTreeMap<SkipReason, usize>,
+    /// How many saved items were unsaved.
+    pub unsaved: usize,
+    /// When the comments seen during the scan were created.
+    pub comment_ages: AgeRange,
+    /// When the posts seen during the scan were created.
+    pub post_ages: AgeRange,
+    pub deletions: Vec<Deletion>,
+    /// Problems that didn't stop the run, such as items possibly being
+    /// missed.
+    pub warnings: Vec<String>,
+}
+
+impl RunReport {
+    fn new(
+        started_at: f64,
+        comment_ages: AgeRange,
+        post_ages: AgeRange,
+    ) -> Self {
+        RunReport {
+            started_at,
+            finished_at: started_at,
+            cancelled: false,
+            comments: Counts::default(),
+            posts: Counts::default(),
+            subreddits: BTreeMap::new(),
+            skipped: BTreeMap::new(),
+            unsaved: 0,
+            comment_ages,
+            post_ages,
+            deletions: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The deletions that failed.
+    pub(crate) fn failures(&self) -> impl Iterator<Item = &Deletion> {
+        self.deletions.iter().filter(|deletion| !deletion.deleted)
+    }
+
+    fn skip(&mut self, item: &reddit::Object, reason: SkipReason) {
+        *self.skipped.entry(reason).or_insert(0) += 1;
+
+        for counts in self.counts_of(item.kind_name(), item.subreddit()) {
+            counts.skipped += 1;
+        }
+    }
+
+    fn record(&mut self, deletion: Deletion) {
+        let subreddit = Some(deletion.subreddit.as_str())
+            .filter(|subreddit| !subreddit.is_empty());
+
+        for counts in self.counts_of(deletion.kind, subreddit) {
+            if deletion.deleted {
+                counts.deleted += 1;
+            } else {
+                counts.failed += 1;
+            }
+        }
+
+        self.deletions.push(deletion);
+    }
+
+    /// The counts an item of `kind` in `subreddit` adds to.
+    fn counts_of(
+        &mut self,
+        kind: &str,
+        subreddit: Option<&str>,
+    ) -> Vec<&mut Counts> {
+        let mut counts = Vec::with_capacity(2);
+
+        match kind {
+            "comment" => counts.push(&mut self.comments),
+            "post" => counts.push(&mut self.posts),
+            _ => {}
+        }
+
+        if let Some(subreddit) = subreddit {
+            counts.push(
+                self.subreddits.entry(subreddit.to_string()).or_default(),
+            );
+        }
+
+        counts
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub(crate) struct Counts {
+    pub deleted: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// Why an item was left alone.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SkipReason {
+    /// The item's fullname could not be deleted.
+    Malformed,
+    Whitelisted,
+    /// `only_inaccessible` was set and the subreddit is still accessible.
+    Accessible,
+    CommentDepth,
+    /// Created before `created_after`.
+    BeforeLastRun,
+    /// Outside the score window.
+    Score,
+    Trending,
+    /// Left out of the sample.
+    NotSampled,
+}
+
+impl SkipReason {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::Malformed => "malformed name",
+            SkipReason::Whitelisted => "whitelisted subreddit",
+            SkipReason::Accessible => "accessible subreddit",
+            SkipReason::CommentDepth => "comment depth",
+            SkipReason::BeforeLastRun => "created before the last run",
+            SkipReason::Score => "score",
+            SkipReason::Trending => "trending",
+            SkipReason::NotSampled => "not sampled",
+        }
+    }
+}
+
+/// The creation times of the oldest and newest of some items, in seconds
+/// since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct AgeRange {
+    pub oldest: Option<f64>,
+    pub newest: Option<f64>,
+}
+
+impl AgeRange {
+    pub(crate) fn of(items: &[reddit::Object]) -> Self {
+        items.iter().filter_map(|item| item.created_utc()).fold(
+            Self::default(),
+            |range, created| Self {
+                oldest: Some(range.oldest.map_or(created, |t| t.min(created))),
+                newest: Some(range.newest.map_or(created, |t| t.max(created))),
+            },
+        )
+    }
+}
+
+/// The outcome of deleting a single comment or post.
+#[derive(Debug, Serialize)]
+pub(crate) struct Deletion {
+    pub kind: &'static str,
+    pub name: String,
+    pub subreddit: String,
+    pub deleted: bool,
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, MockClient, MockState};
+
+    /// `count` comments split into listing pages.
+    fn comment_pages(count: usize) -> Vec<Vec<reddit::Object>> {
+        let comments: Vec<_> = (0..count)
+            .map(|i| testing::comment(&i.to_string(), "rust"))
+            .collect();
+
+        comments
+            .chunks(LISTING_LIMIT as usize)
+            .map(|page| page.to_vec())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn listing_page_is_retried_within_listing_retries() {
+        let client = MockClient::new(MockState {
+            comments: comment_pages(60),
+            posts: vec![vec![testing::post("a", "rust")]],
+            failures: vec![("get_comments", 2)].into_iter().collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            listing_retries: 2,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("get_comments").len(), 4);
+        assert_eq!(report.comments.deleted, 60);
+        assert_eq!(report.posts.deleted, 1);
+    }
+
+    #[tokio::test]
+    async fn listing_fails_once_listing_retries_are_used_up() {
+        let client = MockClient::new(MockState {
+            comments: comment_pages(60),
+            failures: vec![("get_comments", 3)].into_iter().collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            listing_retries: 2,
+            delete_retries: 5,
+            ..testing::params(&settings)
+        };
+
+        let err = testing::app(&client).regreddit(&params).await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Network);
+        assert!(client.deleted().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deletes_use_their_own_retry_budget() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            failures: vec![("get_comments", 1), ("delete_link", 3)]
+                .into_iter()
+                .collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            listing_retries: 1,
+            delete_retries: 3,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("delete_link").len(), 4);
+        assert_eq!(client.deleted(), vec!["t1_a"]);
+        assert_eq!(report.comments.deleted, 1);
+
+        // The listing budget does not carry over to deletes.
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            failures: vec![("delete_link", 1)].into_iter().collect(),
+            ..MockState::default()
+        });
+        let params = RegredditParams {
+            listing_retries: 5,
+            delete_retries: 0,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("delete_link").len(), 1);
+        assert_eq!(report.comments.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn sample_deletes_exactly_that_many_items_by_seed() {
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            sample: Some(7),
+            seed: Some(42),
+            ..testing::params(&settings)
+        };
+        let mut picked = Vec::new();
+
+        for _ in 0..2 {
+            let client = MockClient::new(MockState {
+                comments: comment_pages(30),
+                ..MockState::default()
+            });
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+            let mut deleted = client.deleted();
+
+            deleted.sort();
+            assert_eq!(deleted.len(), 7);
+            assert_eq!(report.skipped[&SkipReason::NotSampled], 23);
+            picked.push(deleted);
+        }
+
+        assert_eq!(picked[0], picked[1]);
+    }
+
+    #[tokio::test]
+    async fn over_18_off_is_reported_as_a_warning() {
+        let settings = testing::settings(&[]);
+
+        for &(over_18, set_over_18, warnings) in
+            &[(false, false, 1), (false, true, 0), (true, false, 0)]
+        {
+            let client = MockClient::new(MockState {
+                over_18,
+                ..MockState::default()
+            });
+            let params = RegredditParams {
+                set_over_18,
+                ..testing::params(&settings)
+            };
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+
+            assert_eq!(report.warnings.len(), warnings);
+        }
+    }
+
+    #[test]
+    fn regreddit_finishes_on_the_basic_scheduler() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = MockClient::new(MockState {
+            comments: comment_pages(60),
+            posts: vec![vec![testing::post("a", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            concurrency: Concurrency::Unlimited,
+            ..testing::params(&settings)
+        };
+
+        let report = runtime
+            .block_on(testing::app(&client).regreddit(&params))
+            .unwrap();
+
+        assert_eq!(report.comments.deleted, 60);
+        assert_eq!(report.posts.deleted, 1);
+    }
+
+    #[tokio::test]
+    async fn ignore_whitelist_deletes_whitelisted_items() {
+        let settings = testing::settings(&["rust"]);
+
+        for &ignore_whitelist in &[false, true] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![
+                    testing::comment("a", "rust"),
+                    testing::comment("b", "AskReddit"),
+                ]],
+                ..MockState::default()
+            });
+            let params = RegredditParams {
+                ignore_whitelist,
+                ..testing::params(&settings)
+            };
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+            let mut deleted = client.deleted();
+
+            deleted.sort();
+
+            if ignore_whitelist {
+                assert_eq!(deleted, vec!["t1_a", "t1_b"]);
+                assert!(report.skipped.is_empty());
+            } else {
+                assert_eq!(deleted, vec!["t1_b"]);
+                assert_eq!(report.skipped[&SkipReason::Whitelisted], 1);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn link_is_submitted_to_each_subreddit() {
+        let client = MockClient::new(MockState {
+            failing_subreddits: vec!["AskReddit".to_string()]
+                .into_iter()
+                .collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+
+        let res = testing::app(&client)
+            .submit_link(&SubmitLinkParams {
+                credentials: &settings.credentials,
+                subreddits: &["rust", "AskReddit", "pics"],
+                title: "A title",
+                url: "https://example.com",
+                send_replies: true,
+            })
+            .await
+            .unwrap();
+        let results: Vec<_> = res
+            .submissions
+            .iter()
+            .map(|s| {
+                (s.subreddit.as_str(), s.name.as_deref(), s.error.is_some())
+            })
+            .collect();
+
+        assert_eq!(client.calls("submit"), vec!["rust", "AskReddit", "pics"]);
+        assert_eq!(
+            results,
+            vec![
+                ("rust", Some("t3_rust"), false),
+                ("AskReddit", None, true),
+                ("pics", Some("t3_pics"), false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn max_listing_pages_stops_scanning() {
+        let client = MockClient::new(MockState {
+            comments: comment_pages(200),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            max_listing_pages: Some(2),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("get_comments").len(), 2);
+        assert_eq!(report.comments.deleted, 2 * LISTING_LIMIT as usize);
+    }
+
+    #[tokio::test]
+    async fn thorough_deletes_comments_behind_more_stubs() {
+        let settings = testing::settings(&[]);
+
+        for &thorough in &[false, true] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![
+                    testing::comment("a", "rust"),
+                    reddit::Object::More {
+                        children: vec!["b".to_string(), "c".to_string()],
+                    },
+                ]],
+                info: vec![
+                    testing::comment("b", "rust"),
+                    testing::comment("c", "rust"),
+                ],
+                ..MockState::default()
+            });
+            let params = RegredditParams {
+                thorough,
+                ..testing::params(&settings)
+            };
+
+            testing::app(&client).regreddit(&params).await.unwrap();
+
+            let mut deleted = client.deleted();
+
+            deleted.sort();
+
+            if thorough {
+                assert_eq!(client.calls("get_info"), vec!["t1_b,t1_c"]);
+                assert_eq!(deleted, vec!["t1_a", "t1_b", "t1_c"]);
+            } else {
+                assert!(client.calls("get_info").is_empty());
+                assert_eq!(deleted, vec!["t1_a"]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn page_ending_in_a_more_stub_is_followed_by_the_next() {
+        let client = MockClient::new(MockState {
+            comments: vec![
+                vec![
+                    testing::comment("a", "rust"),
+                    reddit::Object::More {
+                        children: vec!["b".to_string()],
+                    },
+                ],
+                vec![testing::comment("c", "rust")],
+            ],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+
+        testing::app(&client)
+            .regreddit(&testing::params(&settings))
+            .await
+            .unwrap();
+
+        let mut deleted = client.deleted();
+
+        deleted.sort();
+
+        assert_eq!(client.calls("get_comments"), vec!["", "t1_a"]);
+        assert_eq!(deleted, vec!["t1_a", "t1_c"]);
+    }
+
+    #[tokio::test]
+    async fn items_without_a_name_are_not_deleted() {
+        let mut unnamed = testing::comment("a", "rust");
+
+        if let reddit::Object::Comment { ref mut name, .. } = unnamed {
+            name.clear();
+        }
+
+        let client = MockClient::new(MockState {
+            comments: vec![vec![unnamed, testing::comment("b", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+
+        let report = testing::app(&client)
+            .regreddit(&testing::params(&settings))
+            .await
+            .unwrap();
+
+        assert_eq!(client.calls("delete_link"), vec!["t1_b"]);
+        assert_eq!(report.skipped[&SkipReason::Malformed], 1);
+    }
+
+    #[test]
+    fn auto_concurrency_is_within_bounds() {
+        for millis in &[0, 1, 10, 500, 1000, 1500, 5000, 60_000, u64::MAX] {
+            let permits = auto_concurrency(Duration::from_millis(*millis));
+
+            assert!((1..=MAX_AUTO_CONCURRENCY).contains(&permits));
+        }
+
+        assert_eq!(auto_concurrency(Duration::from_secs(0)), 1);
+        assert_eq!(
+            auto_concurrency(Duration::from_secs(3600)),
+            MAX_AUTO_CONCURRENCY
+        );
+    }
+
+    #[tokio::test]
+    async fn comment_whitelist_keeps_only_comments() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            posts: vec![vec![testing::post("b", "rust")]],
+            ..MockState::default()
+        });
+        let mut settings = testing::settings(&[]);
+
+        settings.whitelist_comments = vec!["rust".to_string()];
+
+        let report = testing::app(&client)
+            .regreddit(&testing::params(&settings))
+            .await
+            .unwrap();
+
+        assert_eq!(client.deleted(), vec!["t3_b"]);
+        assert_eq!(report.comments.skipped, 1);
+        assert_eq!(report.posts.deleted, 1);
+    }
+
+    #[test]
+    fn subreddit_set_matches_case_insensitively_unless_strict() {
+        let names: HashSet<String> =
+            vec!["AskReddit".to_string()].into_iter().collect();
+        let loose = SubredditSet::new(names.clone(), false);
+        let strict = SubredditSet::new(names, true);
+
+        assert!(loose.contains("AskReddit"));
+        assert!(loose.contains("askreddit"));
+        assert!(strict.contains("AskReddit"));
+        assert!(!strict.contains("askreddit"));
+    }
+
+    #[tokio::test]
+    async fn only_inaccessible_deletes_items_in_banned_subreddits() {
+        let with_detail = |mut item: reddit::Object, subreddit_type| {
+            if let reddit::Object::Comment {
+                ref mut sr_detail, ..
+            } = item
+            {
+                *sr_detail = Some(reddit::SubredditDetail {
+                    subreddit_type,
+                    quarantine: false,
+                    user_is_moderator: false,
+                });
+            }
+
+            item
+        };
+        let client = MockClient::new(MockState {
+            comments: vec![vec![
+                with_detail(testing::comment("a", "banned"), None),
+                with_detail(
+                    testing::comment("b", "rust"),
+                    Some("public".to_string()),
+                ),
+            ]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            only_inaccessible: true,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.deleted(), vec!["t1_a"]);
+        assert_eq!(report.skipped[&SkipReason::Accessible], 1);
+    }
+
+    #[tokio::test]
+    async fn consecutive_auth_failures_stop_the_run() {
+        let client = MockClient::new(MockState {
+            comments: comment_pages(10),
+            successful_auths: Some(1),
+            expired_tokens: vec!["token-1".to_string()].into_iter().collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            max_auth_failures: 2,
+            delete_retries: 3,
+            concurrency: Concurrency::Fixed(1),
+            ..testing::params(&settings)
+        };
+
+        let err = testing::app(&client).regreddit(&params).await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::Authentication);
+        // The first authentication and the two failed ones.
+        assert_eq!(client.calls("basic_auth").len(), 3);
+        assert_eq!(client.calls("delete_link").len(), 2);
+        assert!(client.deleted().is_empty());
+    }
+
+    #[test]
+    fn age_range_spans_the_oldest_and_newest_items() {
+        let items = vec![
+            testing::created_at(testing::comment("a", "rust"), 1.5e9),
+            reddit::Object::More {
+                children: vec!["b".to_string()],
+            },
+            testing::created_at(testing::post("c", "rust"), 1.4e9),
+            testing::created_at(testing::comment("d", "rust"), 1.6e9),
+        ];
+
+        assert_eq!(
+            AgeRange::of(&items),
+            AgeRange {
+                oldest: Some(1.4e9),
+                newest: Some(1.6e9),
+            }
+        );
+        assert_eq!(AgeRange::of(&[]), AgeRange::default());
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_deleted_without_listing() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("live", "rust")]],
+            ..MockState::default()
+        });
+        let snapshot = Snapshot {
+            comments: vec![
+                testing::comment("a", "rust"),
+                testing::comment("b", "pics"),
+            ],
+            posts: vec![testing::post("c", "rust")],
+            saved: Vec::new(),
+        };
+        let settings = testing::settings(&["pics"]);
+        let params = RegredditParams {
+            snapshot: Some(&snapshot),
+            ..testing::params(&settings)
+        };
+
+        testing::app(&client).regreddit(&params).await.unwrap();
+
+        let mut deleted = client.deleted();
+
+        deleted.sort();
+        assert_eq!(deleted, vec!["t1_a", "t3_c"]);
+        assert!(client.calls("get_comments").is_empty());
+        assert!(client.calls("get_posts").is_empty());
+        assert!(client.calls("get_prefs").is_empty());
+    }
+
+    #[tokio::test]
+    async fn self_post_text_file_is_submitted_as_is() {
+        let path = testing::temp_path("body.txt");
+        let body = "Cafe\u{301} and re\u{301}sume\u{301} 🦀";
+
+        std::fs::write(&path, body).unwrap();
+
+        let client = MockClient::default();
+        let settings = testing::settings(&[]);
+        let res = testing::app(&client)
+            .submit_self_post(&SubmitSelfPostParams {
+                credentials: &settings.credentials,
+                subreddits: &["rust"],
+                title: "Rust 🦀 release",
+                text: None,
+                text_file: path.to_str(),
+                richtext_json: None,
+                richtext_json_file: None,
+                send_replies: true,
+            })
+            .await;
+
+        std::fs::remove_file(path).unwrap();
+        assert!(res.unwrap().submissions[0].error.is_none());
+
+        let submitted = client.state.lock().unwrap().submitted.clone();
+
+        match submitted[0] {
+            reddit::Post::SelfPost {
+                ref title,
+                body: reddit::SelfPostBody::Text(ref text),
+                ..
+            } => {
+                assert_eq!(title, "Rust 🦀 release");
+                assert_eq!(text, body);
+            }
+            _ => panic!("expected a text self-post"),
+        }
+    }
+
+    #[tokio::test]
+    async fn non_utf8_text_file_is_invalid_input() {
+        let path = testing::temp_path("latin1.txt");
+
+        std::fs::write(&path, b"Caf\xe9").unwrap();
+
+        let res = read_text_file(path.to_str().unwrap()).await;
+
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn only_items_within_the_score_window_are_deleted() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![
+                testing::scored(testing::comment("below", "rust"), -1),
+                testing::scored(testing::comment("min", "rust"), 0),
+                testing::scored(testing::comment("within", "rust"), 5),
+                testing::scored(testing::comment("max", "rust"), 10),
+                testing::scored(testing::comment("above", "rust"), 11),
+            ]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            score_min: Some(0),
+            score_max: Some(10),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+        let mut deleted = client.deleted();
+
+        deleted.sort();
+        assert_eq!(deleted, vec!["t1_max", "t1_min", "t1_within"]);
+        assert_eq!(report.skipped[&SkipReason::Score], 2);
+    }
+
+    #[tokio::test]
+    async fn inverted_score_window_is_rejected() {
+        let client = MockClient::default();
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            score_min: Some(10),
+            score_max: Some(0),
+            ..testing::params(&settings)
+        };
+
+        let err = testing::app(&client).regreddit(&params).await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(client.calls("basic_auth").is_empty());
+    }
+
+    fn moderated(mut item: reddit::Object, moderator: bool) -> reddit::Object {
+        if let reddit::Object::Comment {
+            ref mut sr_detail, ..
+        } = item
+        {
+            *sr_detail = Some(reddit::SubredditDetail {
+                subreddit_type: Some("public".to_string()),
+                quarantine: false,
+                user_is_moderator: moderator,
+            });
+        }
+
+        item
+    }
+
+    #[tokio::test]
+    async fn mod_action_is_taken_only_in_moderated_subreddits() {
+        for &(action, method) in &[
+            (ModAction::Approve, "approve"),
+            (ModAction::Remove, "remove"),
+        ] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![
+                    moderated(testing::comment("a", "modded"), true),
+                    moderated(testing::comment("b", "other"), false),
+                ]],
+                ..MockState::default()
+            });
+            let settings = testing::settings(&[]);
+            let params = RegredditParams {
+                mod_action: Some(action),
+                ..testing::params(&settings)
+            };
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+
+            let mut deleted = client.deleted();
+
+            deleted.sort();
+
+            assert_eq!(client.calls(method), vec!["t1_a"]);
+            assert_eq!(deleted, vec!["t1_a", "t1_b"]);
+            assert!(!report
+                .warnings
+                .contains(&MISSING_SR_DETAIL_WARNING.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn mod_action_warns_about_items_without_sr_detail() {
+        let client = MockClient::default();
+        let settings = testing::settings(&[]);
+        let snapshot = Snapshot {
+            comments: vec![testing::comment("a", "modded")],
+            posts: Vec::new(),
+            saved: Vec::new(),
+        };
+        let params = RegredditParams {
+            mod_action: Some(ModAction::Remove),
+            snapshot: Some(&snapshot),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert!(client.calls("remove").is_empty());
+        assert_eq!(client.deleted(), vec!["t1_a"]);
+        assert_eq!(report.warnings, vec![MISSING_SR_DETAIL_WARNING]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn thousands_of_items_are_deleted_concurrently() {
+        let count = 5000;
+        let client = MockClient::default();
+        let settings = testing::settings(&[]);
+        let snapshot = Snapshot {
+            comments: (0..count)
+                .map(|i| testing::comment(&format!("c{}", i), "rust"))
+                .collect(),
+            posts: (0..count)
+                .map(|i| testing::post(&format!("p{}", i), "rust"))
+                .collect(),
+            saved: Vec::new(),
+        };
+        let params = RegredditParams {
+            snapshot: Some(&snapshot),
+            concurrency: Concurrency::Fixed(256),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        let deleted: HashSet<String> = client.deleted().into_iter().collect();
+
+        assert_eq!(deleted.len(), 2 * count);
+        assert_eq!(
+            client.state.lock().unwrap().delete_clients.len(),
+            1,
+            "every worker shares the app's client"
+        );
+        assert_eq!(report.comments.deleted, count);
+        assert_eq!(report.posts.deleted, count);
+        assert_eq!(report.deletions.len(), 2 * count);
+    }
+
+    #[tokio::test]
+    async fn trending_posts_are_kept() {
+        let client = MockClient::new(MockState {
+            posts: vec![vec![
+                testing::post("hot", "rust"),
+                testing::post("cold", "rust"),
+            ]],
+            top: vec![(
+                "rust".to_string(),
+                vec![testing::post("hot", "rust"), testing::post("x", "rust")],
+            )]
+            .into_iter()
+            .collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            keep_if_trending: true,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("get_subreddit_top"), vec!["rust"]);
+        assert_eq!(client.deleted(), vec!["t3_cold"]);
+        assert_eq!(report.skipped[&SkipReason::Trending], 1);
+    }
+
+    #[tokio::test]
+    async fn slow_deletes_keep_only_the_workers_in_flight() {
+        let client = MockClient::new(MockState {
+            delete_delay: Duration::from_millis(5),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let snapshot = Snapshot {
+            comments: (0..50)
+                .map(|i| testing::comment(&i.to_string(), "rust"))
+                .collect(),
+            posts: Vec::new(),
+            saved: Vec::new(),
+        };
+        let params = RegredditParams {
+            snapshot: Some(&snapshot),
+            concurrency: Concurrency::Fixed(4),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.deleted().len(), 50);
+        assert_eq!(report.comments.deleted, 50);
+        assert_eq!(client.state.lock().unwrap().max_in_flight, 4);
+    }
+
+    #[tokio::test]
+    async fn slow_consumer_holds_back_the_workers() {
+        let count = 300;
+        let workers = 8;
+        let client = MockClient::default();
+        let shared: Arc<Box<dyn client::Client>> =
+            Arc::new(Box::new(client.clone()));
+        let session = Arc::new(Session::new(
+            shared.clone(),
+            testing::settings(&[]).credentials,
+            "token-0".to_string(),
+            3,
+            Backoff::new(RETRY_DELAY, MAX_RETRY_DELAY),
+        ));
+        let items = (0..count)
+            .map(|i| testing::comment(&i.to_string(), "rust"))
+            .collect();
+        let mut received = 0;
+        let mut max_undelivered = 0;
+
+        delete_concurrently(&shared, &session, items, workers, None, 0, |_| {
+            // Deletes done but not yet taken are either in the channel or
+            // held by a worker waiting to send.
+            let done = client.deleted().len();
+
+            received += 1;
+            max_undelivered = max_undelivered.max(done + 1 - received);
+            tokio::time::delay_for(Duration::from_millis(1))
+        })
+        .await;
+
+        let deleted: HashSet<String> = client.deleted().into_iter().collect();
+
+        assert_eq!(received, count);
+        assert_eq!(deleted.len(), count);
+        assert!(
+            max_undelivered <= RESULTS_BUFFER + workers,
+            "{} results were waiting",
+            max_undelivered
+        );
+        assert!(max_undelivered >= RESULTS_BUFFER, "{}", max_undelivered);
+    }
+
+    #[tokio::test]
+    async fn comment_depth_goes_by_the_parent() {
+        let reply = |id| {
+            let mut item = testing::comment(id, "rust");
+
+            if let reddit::Object::Comment {
+                ref mut parent_id, ..
+            } = item
+            {
+                *parent_id = "t1_top".to_string();
+            }
+
+            item
+        };
+
+        for &(depth, deleted) in &[
+            (CommentDepth::TopLevel, ["t1_top", "t3_post"]),
+            (CommentDepth::Replies, ["t1_reply", "t3_post"]),
+        ] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![
+                    testing::comment("top", "rust"),
+                    reply("reply"),
+                ]],
+                posts: vec![vec![testing::post("post", "rust")]],
+                ..MockState::default()
+            });
+            let settings = testing::settings(&[]);
+            let params = RegredditParams {
+                comment_depth: Some(depth),
+                ..testing::params(&settings)
+            };
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+
+            let mut actual = client.deleted();
+
+            actual.sort();
+
+            assert_eq!(actual, deleted);
+            assert_eq!(report.skipped[&SkipReason::CommentDepth], 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn prefs_are_backed_up_before_changing_and_restored() {
+        let client = MockClient::new(MockState {
+            other_prefs: vec![("lang".to_string(), "en".into())]
+                .into_iter()
+                .collect(),
+            ..MockState::default()
+        });
+        let backup = testing::temp_path("prefs.json");
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            set_over_18: true,
+            backup_prefs: Some(&backup),
+            ..testing::params(&settings)
+        };
+
+        testing::app(&client).regreddit(&params).await.unwrap();
+
+        let prefs: reddit::Prefs =
+            serde_json::from_slice(&std::fs::read(&backup).unwrap()).unwrap();
+
+        std::fs::remove_file(&backup).unwrap();
+
+        assert!(!prefs.over_18);
+        assert_eq!(prefs.other["lang"], "en");
+
+        // As if it had been turned on since.
+        client.state.lock().unwrap().over_18 = true;
+
+        testing::app(&client)
+            .restore_prefs(&RestorePrefsParams {
+                credentials: &settings.credentials,
+                prefs: &prefs,
+            })
+            .await
+            .unwrap();
+
+        let state = client.state.lock().unwrap();
+        let over_18: Vec<_> = state
+            .set_prefs
+            .iter()
+            .map(|prefs| prefs["over_18"].clone())
+            .collect();
+
+        assert_eq!(over_18, vec![true, false, false]);
+        assert_eq!(state.set_prefs[2]["lang"], "en");
+        assert!(!state.over_18);
+    }
+
+    #[tokio::test]
+    async fn prefs_are_backed_up_when_deleting_from_a_snapshot() {
+        let client = MockClient::default();
+        let backup = testing::temp_path("snapshot-prefs.json");
+        let settings = testing::settings(&[]);
+        let snapshot = Snapshot {
+            comments: vec![testing::comment("a", "rust")],
+            posts: Vec::new(),
+            saved: Vec::new(),
+        };
+        let params = RegredditParams {
+            snapshot: Some(&snapshot),
+            backup_prefs: Some(&backup),
+            ..testing::params(&settings)
+        };
+
+        testing::app(&client).regreddit(&params).await.unwrap();
+
+        let prefs: reddit::Prefs =
+            serde_json::from_slice(&std::fs::read(&backup).unwrap()).unwrap();
+
+        std::fs::remove_file(&backup).unwrap();
+
+        assert!(!prefs.over_18);
+        assert_eq!(client.deleted(), vec!["t1_a"]);
+    }
+
+    #[tokio::test]
+    async fn stored_last_run_only_deletes_newer_items() {
+        let path = testing::temp_path("state.json");
+
+        crate::state::State {
+            last_run: Some(1.5e9),
+        }
+        .save(&path)
+        .unwrap();
+
+        let state = crate::state::State::load(&path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        let client = MockClient::new(MockState {
+            comments: vec![vec![
+                testing::created_at(testing::comment("old", "rust"), 1.4e9),
+                testing::created_at(testing::comment("new", "rust"), 1.6e9),
+            ]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            created_after: state.last_run,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.deleted(), vec!["t1_new"]);
+        assert_eq!(report.skipped[&SkipReason::BeforeLastRun], 1);
+    }
+
+    #[tokio::test]
+    async fn refresh_saves_deletes_and_submits_the_post_again() {
+        let client = MockClient::new(MockState {
+            info: vec![testing::post("abc123", "rust")],
+            ..MockState::default()
+        });
+        let backup = testing::temp_path("refresh.json");
+        let settings = testing::settings(&[]);
+
+        let res = testing::app(&client)
+            .refresh(&RefreshParams {
+                credentials: &settings.credentials,
+                id: "t3_abc123",
+                allow_repost: false,
+                send_replies: true,
+                backup: Some(&backup),
+            })
+            .await
+            .unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&backup).unwrap()).unwrap();
+
+        std::fs::remove_file(&backup).unwrap();
+
+        let state = client.state.lock().unwrap();
+        let calls: Vec<(&str, &str)> = state
+            .calls
+            .iter()
+            .map(|(method, arg)| (*method, arg.as_str()))
+            .collect();
+
+        assert_eq!(
+            calls,
+            vec![
+                ("basic_auth", ""),
+                ("get_info", "t3_abc123"),
+                ("delete_link", "t3_abc123"),
+                ("submit", "rust"),
+            ]
+        );
+        assert_eq!(saved["data"]["title"], "Post abc123");
+        assert_eq!(saved["data"]["selftext"], "Text abc123");
+        assert_eq!(res.name.as_deref(), Some("t3_rust"));
+
+        match &state.submitted[..] {
+            [reddit::Post::SelfPost {
+                title,
+                body: reddit::SelfPostBody::Text(text),
+                ..
+            }] => {
+                assert_eq!(title, "Post abc123");
+                assert_eq!(text, "Text abc123");
+            }
+            _ => panic!("expected one self-post"),
+        }
+    }
+
+    #[tokio::test]
+    async fn randomize_order_shuffles_deterministically_by_seed() {
+        let settings = testing::settings(&[]);
+        let deletion_order = |randomize_order| {
+            let settings = &settings;
+
+            async move {
+                let client = MockClient::new(MockState {
+                    comments: comment_pages(2 * LISTING_LIMIT as usize),
+                    ..MockState::default()
+                });
+                let params = RegredditParams {
+                    randomize_order,
+                    seed: Some(42),
+                    // One at a time, so items are deleted in order.
+                    concurrency: Concurrency::Fixed(1),
+                    ..testing::params(settings)
+                };
+
+                testing::app(&client).regreddit(&params).await.unwrap();
+                client.deleted()
+            }
+        };
+
+        let listed = deletion_order(false).await;
+        let shuffled = deletion_order(true).await;
+        let mut sorted = shuffled.clone();
+
+        sorted.sort();
+
+        let mut listed_sorted = listed.clone();
+
+        listed_sorted.sort();
+
+        assert_eq!(listed[..3], ["t1_0", "t1_1", "t1_2"]);
+        assert_eq!(shuffled, deletion_order(true).await);
+        assert_ne!(shuffled, listed);
+        assert_eq!(sorted, listed_sorted);
+        assert!(
+            shuffled[..LISTING_LIMIT as usize]
+                .iter()
+                .any(|name| !listed[..LISTING_LIMIT as usize].contains(name)),
+            "the first page's worth mixes in items from the second page"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_report_is_filled_in() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![
+                testing::created_at(testing::comment("old", "rust"), 1.4e9),
+                testing::created_at(testing::comment("new", "rust"), 1.6e9),
+                testing::comment("kept", "pics"),
+            ]],
+            posts: vec![vec![testing::post("post", "rust")]],
+            saved: vec![vec![testing::post("saved", "pics")]],
+            failures: vec![("delete_link", 1)].into_iter().collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&["pics"]);
+        let params = RegredditParams {
+            unsave: true,
+            concurrency: Concurrency::Fixed(1),
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        let counts =
+            |counts: &Counts| (counts.deleted, counts.failed, counts.skipped);
+
+        assert!(report.started_at > 0.0);
+        assert!(report.finished_at >= report.started_at);
+        assert!(!report.cancelled);
+        assert_eq!(counts(&report.comments), (1, 1, 1));
+        assert_eq!(counts(&report.posts), (1, 0, 0));
+        assert_eq!(counts(&report.subreddits["rust"]), (2, 1, 0));
+        assert_eq!(counts(&report.subreddits["pics"]), (0, 0, 1));
+        assert_eq!(report.skipped[&SkipReason::Whitelisted], 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.unsaved, 1);
+        assert_eq!(report.comment_ages.oldest, Some(1.4e9));
+        assert_eq!(report.comment_ages.newest, Some(1.6e9));
+        assert_eq!(report.post_ages.oldest, Some(1.5e9));
+        assert_eq!(report.post_ages.newest, Some(1.5e9));
+        assert_eq!(report.warnings, vec![OVER_18_WARNING]);
+
+        let deletions: Vec<_> = report
+            .deletions
+            .iter()
+            .map(|deletion| {
+                (
+                    deletion.kind,
+                    deletion.name.as_str(),
+                    deletion.subreddit.as_str(),
+                    deletion.deleted,
+                    deletion.error.as_deref(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            deletions,
+            vec![
+                (
+                    "comment",
+                    "t1_old",
+                    "rust",
+                    false,
+                    Some("delete_link failed")
+                ),
+                ("comment", "t1_new", "rust", true, None),
+                ("post", "t3_post", "rust", true, None),
+            ]
+        );
+        assert_eq!(report.failures().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn unsave_others_only_keeps_your_own_saved_items() {
+        let client = MockClient::new(MockState {
+            saved: vec![vec![
+                testing::post("mine", "rust"),
+                testing::authored_by(testing::comment("theirs", "rust"), "a"),
+                testing::authored_by(
+                    testing::comment("shouty", "rust"),
+                    "SOMEONE",
+                ),
+                testing::authored_by(testing::post("other", "pics"), "b"),
+            ]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let params = RegredditParams {
+            unsave: true,
+            unsave_others_only: true,
+            ..testing::params(&settings)
+        };
+
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+
+        assert_eq!(client.calls("unsave"), vec!["t1_theirs", "t3_other"]);
+        assert_eq!(report.unsaved, 2);
+    }
+}
@@ -0,0 +1,113 @@
+//! How long to wait before trying something again.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// The delay before retrying after `attempt` earlier retries. Without jitter
+/// it is `base` times three to the power of `attempt`, so three times the
+/// previous one, and never more than `cap`. With jitter it is drawn at random
+/// between `base` and that, like decorrelated jitter, which keeps clients that
+/// fail together from retrying in lockstep. Attempt 0 is always `base`.
+pub(crate) fn backoff_delay(
+    attempt: u32,
+    base: Duration,
+    cap: Duration,
+    jitter: bool,
+) -> Duration {
+    let base = base.min(cap);
+    let upper = 3u32
+        .checked_pow(attempt)
+        .and_then(|factor| base.checked_mul(factor))
+        .map_or(cap, |upper| upper.min(cap));
+
+    if !jitter {
+        return upper;
+    }
+
+    let millis = rand::thread_rng()
+        .gen_range(base.as_millis() as u64, upper.as_millis() as u64 + 1);
+
+    Duration::from_millis(millis).clamp(base, upper)
+}
+
+/// The bounds of a backoff, for what keeps its own count of attempts.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Backoff {
+    base: Duration,
+    cap: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(base: Duration, cap: Duration) -> Self {
+        Backoff { base, cap }
+    }
+
+    /// The jittered delay after `attempt` earlier retries.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        backoff_delay(attempt, self.base, self.cap, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_millis(100);
+    const CAP: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn attempt_0_is_base() {
+        assert_eq!(backoff_delay(0, BASE, CAP, false), BASE);
+
+        for _ in 0..100 {
+            assert_eq!(backoff_delay(0, BASE, CAP, true), BASE);
+        }
+    }
+
+    #[test]
+    fn delays_triple_without_jitter_until_capped() {
+        let delays: Vec<_> = (0..7)
+            .map(|attempt| backoff_delay(attempt, BASE, CAP, false))
+            .collect();
+
+        assert_eq!(
+            delays,
+            vec![BASE, BASE * 3, BASE * 9, BASE * 27, BASE * 81, CAP, CAP]
+        );
+        assert_eq!(backoff_delay(u32::MAX, BASE, CAP, false), CAP);
+        assert_eq!(
+            backoff_delay(3, CAP, BASE, false),
+            BASE,
+            "a base above the cap is lowered to it"
+        );
+    }
+
+    #[test]
+    fn jittered_delays_stay_between_base_and_the_unjittered_delay() {
+        for attempt in 0..10 {
+            let upper = backoff_delay(attempt, BASE, CAP, false);
+
+            for _ in 0..1000 {
+                let delay = backoff_delay(attempt, BASE, CAP, true);
+
+                assert!(delay >= BASE && delay <= upper, "{:?}", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn jittered_delays_grow_on_average_until_capped() {
+        let mut totals = [Duration::from_secs(0); 5];
+
+        for _ in 0..1000 {
+            for (attempt, total) in totals.iter_mut().enumerate() {
+                *total += backoff_delay(attempt as u32, BASE, CAP, true);
+            }
+        }
+
+        for pair in totals.windows(2) {
+            assert!(pair[0] < pair[1], "{:?}", totals);
+        }
+    }
+}
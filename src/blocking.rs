@@ -0,0 +1,103 @@
+//! Synchronous wrappers around [`App`] for callers that don't run a Tokio
+//! runtime, in the spirit of `reqwest::blocking`. Each call starts a
+//! single-threaded runtime and blocks on it, so they must not be called from
+//! within a runtime.
+
+// The binary itself always runs inside a runtime and never calls these.
+#![allow(dead_code)]
+
+use std::future::Future;
+
+use crate::app::{
+    App, AppImpl, RefreshParams, RefreshResult, RegredditParams,
+    RestorePrefsParams, RestorePrefsResult, RunReport, ScanResult,
+    SubmitLinkParams, SubmitLinkResult, SubmitSelfPostParams,
+    SubmitSelfPostResult, SubmitVideoParams, SubmitVideoResult,
+};
+use crate::error::Result;
+
+impl AppImpl {
+    pub(crate) fn regreddit_blocking(
+        &self,
+        p: &RegredditParams<'_>,
+    ) -> Result<RunReport> {
+        block_on(self.regreddit(p))
+    }
+
+    pub(crate) fn scan_blocking(
+        &self,
+        p: &RegredditParams<'_>,
+    ) -> Result<ScanResult> {
+        block_on(self.scan(p))
+    }
+
+    pub(crate) fn refresh_blocking(
+        &self,
+        p: &RefreshParams<'_>,
+    ) -> Result<RefreshResult> {
+        block_on(self.refresh(p))
+    }
+
+    pub(crate) fn restore_prefs_blocking(
+        &self,
+        p: &RestorePrefsParams<'_>,
+    ) -> Result<RestorePrefsResult> {
+        block_on(self.restore_prefs(p))
+    }
+
+    pub(crate) fn submit_link_blocking(
+        &self,
+        p: &SubmitLinkParams<'_>,
+    ) -> Result<SubmitLinkResult> {
+        block_on(self.submit_link(p))
+    }
+
+    pub(crate) fn submit_self_post_blocking(
+        &self,
+        p: &SubmitSelfPostParams<'_>,
+    ) -> Result<SubmitSelfPostResult> {
+        block_on(self.submit_self_post(p))
+    }
+
+    pub(crate) fn submit_video_blocking(
+        &self,
+        p: &SubmitVideoParams<'_>,
+    ) -> Result<SubmitVideoResult> {
+        block_on(self.submit_video(p))
+    }
+}
+
+fn block_on<T>(future: impl Future<Output = Result<T>>) -> Result<T> {
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(future)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, MockClient, MockState};
+
+    #[test]
+    fn regreddit_blocking_runs_to_completion() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            posts: vec![vec![testing::post("b", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let app = testing::app(&client);
+        let params = testing::params(&settings);
+
+        let scan = app.scan_blocking(&params).unwrap();
+        let report = app.regreddit_blocking(&params).unwrap();
+
+        assert_eq!(scan.snapshot.comments.len(), 1);
+        assert_eq!(scan.snapshot.posts.len(), 1);
+        assert_eq!(report.comments.deleted, 1);
+        assert_eq!(report.posts.deleted, 1);
+        assert!(report.finished_at >= report.started_at);
+    }
+}
@@ -1,13 +1,18 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
 
 use async_trait::async_trait;
 
+use crate::backoff::backoff_delay;
 use crate::error::{Error, ErrorKind, Result};
+use crate::rate_limit::{Allowance, RateLimiter};
 use crate::reddit;
 use crate::settings;
 
 #[async_trait]
 pub(crate) trait Client: Send + Sync {
+    async fn approve(&self, p: &ApproveParams<'_>) -> Result<ApproveResult>;
     async fn basic_auth(
         &self,
         p: &BasicAuthParams<'_>,
@@ -20,25 +25,76 @@ pub(crate) trait Client: Send + Sync {
         &self,
         p: &GetCommentsParams<'_>,
     ) -> Result<GetCommentsResult>;
+    async fn get_info(&self, p: &GetInfoParams<'_>) -> Result<GetInfoResult>;
     async fn get_posts(&self, p: &GetPostsParams<'_>)
         -> Result<GetPostsResult>;
+    async fn get_prefs(&self, p: &GetPrefsParams<'_>)
+        -> Result<GetPrefsResult>;
+    async fn get_saved(&self, p: &GetSavedParams<'_>)
+        -> Result<GetSavedResult>;
+    async fn get_subreddit_top(
+        &self,
+        p: &GetSubredditTopParams<'_>,
+    ) -> Result<GetSubredditTopResult>;
+    async fn remove(&self, p: &RemoveParams<'_>) -> Result<RemoveResult>;
+    async fn set_prefs(&self, p: &SetPrefsParams<'_>)
+        -> Result<SetPrefsResult>;
     async fn submit(&self, p: &SubmitParams<'_>) -> Result<SubmitResult>;
+    async fn unsave(&self, p: &UnsaveParams<'_>) -> Result<UnsaveResult>;
 }
 
+const WWW_URL: &str = "https://www.reddit.com";
+const OAUTH_URL: &str = "https://oauth.reddit.com";
+/// How many times a request answered with a 429 is sent again.
+const RATE_LIMITED_RETRIES: u32 = 5;
+#[cfg(not(test))]
+const RATE_LIMITED_DELAY: Duration = Duration::from_secs(1);
+#[cfg(test)]
+const RATE_LIMITED_DELAY: Duration = Duration::from_millis(1);
+const MAX_RATE_LIMITED_DELAY: Duration = Duration::from_secs(60);
+
 pub(crate) struct ClientImpl {
     http_client: reqwest::Client,
+    /// Submits answer with a redirect to the new post, which we want to see
+    /// rather than follow.
+    submit_client: reqwest::Client,
+    www_url: String,
+    oauth_url: String,
     user_agent: String,
+    write_limiter: RateLimiter,
+    allowance: Allowance,
 }
 
 pub(crate) struct Params {
     pub user_agent: String,
+    /// How many deletes and submits may be made per minute.
+    pub writes_per_minute: u32,
 }
 
 impl ClientImpl {
     pub(crate) fn new(p: Params) -> Self {
         ClientImpl {
             http_client: reqwest::Client::new(),
+            submit_client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap(),
+            www_url: WWW_URL.to_string(),
+            oauth_url: OAUTH_URL.to_string(),
             user_agent: p.user_agent,
+            write_limiter: RateLimiter::per_minute(p.writes_per_minute, 1),
+            allowance: Allowance::new(),
+        }
+    }
+
+    /// Creates a client sending every request to `base_url` instead of
+    /// Reddit.
+    #[cfg(test)]
+    pub(crate) fn with_base_url(p: Params, base_url: &str) -> Self {
+        ClientImpl {
+            www_url: base_url.to_string(),
+            oauth_url: base_url.to_string(),
+            ..ClientImpl::new(p)
         }
     }
 }
@@ -58,7 +114,7 @@ impl Client for ClientImpl {
 
         match self
             .http_client
-            .post("https://www.reddit.com/api/v1/access_token")
+            .post(&format!("{}/api/v1/access_token", self.www_url))
             .header("User-Agent", &self.user_agent)
             .form(&form)
             .basic_auth(&p.credentials.client_id, Some(&p.credentials.secret))
@@ -69,6 +125,15 @@ impl Client for ClientImpl {
             Err(err) => return Err(Error::new(ErrorKind::Authentication, err)),
         }
 
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(Error::new(
+                ErrorKind::Authentication,
+                "Reddit rejected the client ID or secret (401). Make sure the \
+                app at https://www.reddit.com/prefs/apps is a \"script\" app \
+                and has a redirect URI set",
+            ));
+        }
+
         if res.status() != reqwest::StatusCode::OK {
             eprintln!("Authentication failed with status {}.", res.status());
 
@@ -83,19 +148,47 @@ impl Client for ClientImpl {
         }
     }
 
+    async fn approve(&self, p: &ApproveParams<'_>) -> Result<ApproveResult> {
+        log::debug!("Approving thing...");
+
+        self.write_limiter.acquire().await;
+
+        let res = self
+            .send(
+                self.http_client
+                    .post(&format!("{}/api/approve", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .form(&reddit::ApproveRequestForm { id: p.id }),
+            )
+            .await?;
+        let _res = check_response::<reddit::ModerateResponse>(res).await?;
+
+        Ok(ApproveResult {})
+    }
+
     async fn delete_link(
         &self,
         p: &DeleteLinkParams<'_>,
     ) -> Result<DeleteLinkResult> {
         log::debug!("Deleting link...");
 
+        self.write_limiter.acquire().await;
+
         let res = self
-            .http_client
-            .post("https://oauth.reddit.com/api/del")
-            .header("User-Agent", &self.user_agent)
-            .header("Authorization", format!("Bearer {}", p.access_token))
-            .form(&reddit::DeleteRequestForm { id: p.id })
-            .send()
+            .send(
+                self.http_client
+                    .post(&format!("{}/api/del", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .form(&reddit::DeleteRequestForm { id: p.id }),
+            )
             .await?;
         let _res = check_response::<reddit::DeleteResponse>(res).await?;
 
@@ -109,15 +202,19 @@ impl Client for ClientImpl {
         log::debug!("Getting comments...");
 
         let res = self
-            .http_client
-            .get(&format!(
-                "https://oauth.reddit.com/user/{}/comments",
-                p.username
-            ))
-            .header("User-Agent", &self.user_agent)
-            .header("Authorization", format!("Bearer {}", p.access_token))
-            .query(&p.listing_control)
-            .send()
+            .send(
+                self.http_client
+                    .get(&format!(
+                        "{}/user/{}/comments",
+                        self.oauth_url, p.username
+                    ))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .query(&p.listing_control),
+            )
             .await?;
 
         Ok(GetCommentsResult {
@@ -125,6 +222,27 @@ impl Client for ClientImpl {
         })
     }
 
+    async fn get_info(&self, p: &GetInfoParams<'_>) -> Result<GetInfoResult> {
+        log::debug!("Getting info...");
+
+        let res = self
+            .send(
+                self.http_client
+                    .get(&format!("{}/api/info", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .query(&[("id", p.ids.join(","))]),
+            )
+            .await?;
+
+        Ok(GetInfoResult {
+            response: check_response::<reddit::Object>(res).await?,
+        })
+    }
+
     async fn get_posts(
         &self,
         p: &GetPostsParams<'_>,
@@ -132,15 +250,19 @@ impl Client for ClientImpl {
         log::debug!("Getting posts...");
 
         let res = self
-            .http_client
-            .get(&format!(
-                "https://oauth.reddit.com/user/{}/submitted",
-                p.username,
-            ))
-            .header("User-Agent", &self.user_agent)
-            .header("Authorization", format!("Bearer {}", p.access_token))
-            .query(&p.listing_control)
-            .send()
+            .send(
+                self.http_client
+                    .get(&format!(
+                        "{}/user/{}/submitted",
+                        self.oauth_url, p.username,
+                    ))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .query(&p.listing_control),
+            )
             .await?;
 
         Ok(GetPostsResult {
@@ -148,7 +270,134 @@ impl Client for ClientImpl {
         })
     }
 
+    async fn get_prefs(
+        &self,
+        p: &GetPrefsParams<'_>,
+    ) -> Result<GetPrefsResult> {
+        log::debug!("Getting prefs...");
+
+        let res = self
+            .send(
+                self.http_client
+                    .get(&format!("{}/api/v1/me/prefs", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    ),
+            )
+            .await?;
+
+        Ok(GetPrefsResult {
+            prefs: check_response::<reddit::Prefs>(res).await?,
+        })
+    }
+
+    async fn get_saved(
+        &self,
+        p: &GetSavedParams<'_>,
+    ) -> Result<GetSavedResult> {
+        log::debug!("Getting saved items...");
+
+        let res = self
+            .send(
+                self.http_client
+                    .get(&format!(
+                        "{}/user/{}/saved",
+                        self.oauth_url, p.username,
+                    ))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .query(&p.listing_control),
+            )
+            .await?;
+
+        Ok(GetSavedResult {
+            response: check_response::<reddit::Object>(res).await?,
+        })
+    }
+
+    async fn get_subreddit_top(
+        &self,
+        p: &GetSubredditTopParams<'_>,
+    ) -> Result<GetSubredditTopResult> {
+        log::debug!("Getting top posts of r/{}...", p.subreddit);
+
+        let res = self
+            .send(
+                self.http_client
+                    .get(&format!("{}/r/{}/top", self.oauth_url, p.subreddit,))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .query(&[("t", p.time)])
+                    .query(&p.listing_control),
+            )
+            .await?;
+
+        Ok(GetSubredditTopResult {
+            response: check_response::<reddit::Object>(res).await?,
+        })
+    }
+
+    async fn remove(&self, p: &RemoveParams<'_>) -> Result<RemoveResult> {
+        log::debug!("Removing thing...");
+
+        self.write_limiter.acquire().await;
+
+        let res = self
+            .send(
+                self.http_client
+                    .post(&format!("{}/api/remove", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .form(&reddit::RemoveRequestForm {
+                        id: p.id,
+                        spam: false,
+                    }),
+            )
+            .await?;
+        let _res = check_response::<reddit::ModerateResponse>(res).await?;
+
+        Ok(RemoveResult {})
+    }
+
+    async fn set_prefs(
+        &self,
+        p: &SetPrefsParams<'_>,
+    ) -> Result<SetPrefsResult> {
+        log::debug!("Setting prefs...");
+
+        let res = self
+            .send(
+                self.http_client
+                    .patch(&format!("{}/api/v1/me/prefs", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header(
+                        "Authorization",
+                        format!("Bearer {}", p.access_token),
+                    )
+                    .json(p.prefs),
+            )
+            .await?;
+        let _res = check_response::<reddit::Prefs>(res).await?;
+
+        Ok(SetPrefsResult {})
+    }
+
     async fn submit(&self, p: &SubmitParams<'_>) -> Result<SubmitResult> {
+        let name;
+
+        self.write_limiter.acquire().await;
+
         match &p.post {
             reddit::Post::Link {
                 ref subreddit,
@@ -158,32 +407,29 @@ impl Client for ClientImpl {
                 log::info!("Making POST request to Reddit...");
 
                 let res = self
-                    .http_client
-                    .post("https://oauth.reddit.com/api/submit")
-                    .header("User-Agent", &self.user_agent)
-                    .header(
-                        "Authorization",
-                        format!("Bearer {}", p.access_token),
+                    .send(
+                        self.submit_client
+                            .post(&format!("{}/api/submit", self.oauth_url))
+                            .header("User-Agent", &self.user_agent)
+                            .header(
+                                "Authorization",
+                                format!("Bearer {}", p.access_token),
+                            )
+                            .form(&reddit::SubmitRequest {
+                                subreddit,
+                                title,
+                                kind: "link".to_string(),
+                                url: Some(&url.to_string()),
+                                resubmit: true,
+                                text: None,
+                                richtext_json: None,
+                                video_poster_url: None,
+                                send_replies: p.send_replies,
+                                api_type: "json",
+                            }),
                     )
-                    .form(&reddit::SubmitRequest {
-                        subreddit,
-                        title,
-                        kind: "link".to_string(),
-                        url: Some(&url.to_string()),
-                        resubmit: true,
-                        text: None,
-                        richtext_json: None,
-                    })
-                    .send()
                     .await?;
-                let res = check_response::<reddit::SubmitResponse>(res).await?;
-
-                if !res.success {
-                    return Err(Error::new(
-                        ErrorKind::Reddit,
-                        "submit unsuccessful",
-                    ));
-                }
+                name = check_submit_response(res).await?;
 
                 log::info!("Successfully submitted a link.");
             }
@@ -205,6 +451,9 @@ impl Client for ClientImpl {
                             resubmit: true,
                             text: Some(text),
                             richtext_json: None,
+                            video_poster_url: None,
+                            send_replies: p.send_replies,
+                            api_type: "json",
                         }
                     }
                     reddit::SelfPostBody::RichtextJson(ref richtext_json) => {
@@ -216,6 +465,9 @@ impl Client for ClientImpl {
                             resubmit: true,
                             text: None,
                             richtext_json: Some(richtext_json),
+                            video_poster_url: None,
+                            send_replies: p.send_replies,
+                            api_type: "json",
                         }
                     }
                 }
@@ -223,33 +475,243 @@ impl Client for ClientImpl {
                 log::debug!("Making POST request to Reddit...");
 
                 let res = self
-                    .http_client
-                    .post("https://oauth.reddit.com/api/submit")
+                    .send(
+                        self.submit_client
+                            .post(&format!("{}/api/submit", self.oauth_url))
+                            .header("User-Agent", &self.user_agent)
+                            .header(
+                                "Authorization",
+                                format!("Bearer {}", p.access_token),
+                            )
+                            .form(&request),
+                    )
+                    .await?;
+                name = check_submit_response(res).await?;
+
+                log::debug!("Successfully submitted a self-post.");
+            }
+            reddit::Post::Video {
+                ref subreddit,
+                ref title,
+                ref video_path,
+                ref thumbnail_path,
+            } => {
+                log::info!("Uploading video...");
+
+                let video_url =
+                    self.upload_media(p.access_token, video_path).await?;
+
+                log::info!("Uploading thumbnail...");
+
+                let thumbnail_url =
+                    self.upload_media(p.access_token, thumbnail_path).await?;
+
+                log::debug!("Making POST request to Reddit...");
+
+                let res = self
+                    .send(
+                        self.submit_client
+                            .post(&format!("{}/api/submit", self.oauth_url))
+                            .header("User-Agent", &self.user_agent)
+                            .header(
+                                "Authorization",
+                                format!("Bearer {}", p.access_token),
+                            )
+                            .form(&reddit::SubmitRequest {
+                                subreddit,
+                                title,
+                                kind: "video".to_string(),
+                                url: Some(&video_url),
+                                resubmit: true,
+                                text: None,
+                                richtext_json: None,
+                                video_poster_url: Some(&thumbnail_url),
+                                send_replies: p.send_replies,
+                                api_type: "json",
+                            }),
+                    )
+                    .await?;
+                name = check_submit_response(res).await?;
+
+                log::info!("Successfully submitted a video.");
+            }
+        }
+
+        Ok(SubmitResult { name })
+    }
+
+    async fn unsave(&self, p: &UnsaveParams<'_>) -> Result<UnsaveResult> {
+        log::debug!("Unsaving thing...");
+
+        self.write_limiter.acquire().await;
+
+        let res = self
+            .send(
+                self.http_client
+                    .post(&format!("{}/api/unsave", self.oauth_url))
                     .header("User-Agent", &self.user_agent)
                     .header(
                         "Authorization",
                         format!("Bearer {}", p.access_token),
                     )
-                    .form(&request)
-                    .send()
-                    .await?;
-                let res = check_response::<reddit::SubmitResponse>(res).await?;
+                    .form(&reddit::UnsaveRequestForm { id: p.id }),
+            )
+            .await?;
+        let _res = check_response::<reddit::UnsaveResponse>(res).await?;
+
+        Ok(UnsaveResult {})
+    }
+}
+
+impl ClientImpl {
+    /// Sends a request to the OAuth API, holding it back while Reddit's rate
+    /// limit allowance is used up. A request answered with a 429 anyway is
+    /// backed off from and sent again.
+    async fn send(
+        &self,
+        mut request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+
+        loop {
+            self.allowance.wait().await;
+
+            let next = request.try_clone();
+            let res = request.send().await?;
 
-                if !res.success {
-                    return Err(Error::new(
-                        ErrorKind::Reddit,
-                        "submit unsuccessful",
-                    ));
+            self.allowance.update(res.headers());
+
+            match next {
+                Some(next)
+                    if res.status()
+                        == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        && attempt < RATE_LIMITED_RETRIES =>
+                {
+                    let delay = backoff_delay(
+                        attempt,
+                        RATE_LIMITED_DELAY,
+                        MAX_RATE_LIMITED_DELAY,
+                        true,
+                    );
+
+                    attempt += 1;
+
+                    log::warn!(
+                        "Rate limited. Retrying in {:.1}s ({}/{})...",
+                        delay.as_secs_f64(),
+                        attempt,
+                        RATE_LIMITED_RETRIES
+                    );
+                    tokio::time::delay_for(delay).await;
+                    request = next;
                 }
+                _ => return Ok(res),
+            }
+        }
+    }
 
-                log::debug!("Successfully submitted a self-post.");
+    /// Uploads a media file through a media asset lease and returns the URL
+    /// it can be submitted with.
+    async fn upload_media(
+        &self,
+        access_token: &str,
+        path: &Path,
+    ) -> Result<String> {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid media file path {}", path.display()),
+                ))
+            }
+        };
+        let mimetype = media_mimetype(path)?;
+        let bytes = tokio::fs::read(path).await?;
+
+        log::debug!("Requesting upload lease for {}...", file_name);
+
+        let res = self
+            .send(
+                self.http_client
+                    .post(&format!("{}/api/media/asset.json", self.oauth_url))
+                    .header("User-Agent", &self.user_agent)
+                    .header("Authorization", format!("Bearer {}", access_token))
+                    .form(&reddit::MediaAssetRequest {
+                        filepath: file_name,
+                        mimetype,
+                    }),
+            )
+            .await?;
+        let lease = check_response::<reddit::MediaAssetResponse>(res)
+            .await?
+            .args;
+        let action = if lease.action.starts_with("//") {
+            format!("https:{}", lease.action)
+        } else {
+            lease.action
+        };
+        let mut key = None;
+        let mut form = reqwest::multipart::Form::new();
+
+        for field in lease.fields {
+            if field.name == "key" {
+                key = Some(field.value.clone());
             }
+
+            form = form.text(field.name, field.value);
         }
 
-        Ok(SubmitResult {})
+        let key = match key {
+            Some(key) => key,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Reddit,
+                    "upload lease is missing a key",
+                ))
+            }
+        };
+
+        form = form.part(
+            "file",
+            reqwest::multipart::Part::bytes(bytes)
+                .file_name(file_name.to_string())
+                .mime_str(mimetype)?,
+        );
+
+        log::debug!("Uploading {}...", file_name);
+
+        let res = self
+            .http_client
+            .post(&action)
+            .multipart(form)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            log::error!(
+                "Media upload returned bad status {}: {}",
+                res.status(),
+                res.text().await?
+            );
+
+            return Err(Error::new(ErrorKind::Reddit, "media upload failed"));
+        }
+
+        Ok(format!("{}/{}", action, key))
     }
 }
 
+#[derive(Clone, Copy)]
+pub(crate) struct ApproveParams<'a> {
+    pub access_token: &'a str,
+    /// The fullname of the thing to approve.
+    pub id: &'a str,
+}
+
+pub(crate) struct ApproveResult {}
+
+#[derive(Clone, Copy)]
 pub(crate) struct BasicAuthParams<'a> {
     pub credentials: &'a settings::Credentials,
 }
@@ -259,6 +721,7 @@ pub(crate) struct BasicAuthResult {
     pub access_token: String,
 }
 
+#[derive(Clone, Copy)]
 pub(crate) struct DeleteLinkParams<'a> {
     pub access_token: &'a str,
     pub id: &'a str,
@@ -266,6 +729,7 @@ pub(crate) struct DeleteLinkParams<'a> {
 
 pub(crate) struct DeleteLinkResult {}
 
+#[derive(Clone, Copy)]
 pub(crate) struct GetCommentsParams<'a> {
     pub access_token: &'a str,
     pub username: &'a str,
@@ -276,6 +740,18 @@ pub(crate) struct GetCommentsResult {
     pub response: reddit::Object,
 }
 
+#[derive(Clone, Copy)]
+pub(crate) struct GetInfoParams<'a> {
+    pub access_token: &'a str,
+    /// Fullnames of the things to get.
+    pub ids: &'a [String],
+}
+
+pub(crate) struct GetInfoResult {
+    pub response: reddit::Object,
+}
+
+#[derive(Clone, Copy)]
 pub(crate) struct GetPostsParams<'a> {
     pub access_token: &'a str,
     pub username: &'a str,
@@ -286,24 +762,163 @@ pub(crate) struct GetPostsResult {
     pub response: reddit::Object,
 }
 
+#[derive(Clone, Copy)]
+pub(crate) struct GetPrefsParams<'a> {
+    pub access_token: &'a str,
+}
+
+pub(crate) struct GetPrefsResult {
+    pub prefs: reddit::Prefs,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct GetSavedParams<'a> {
+    pub access_token: &'a str,
+    pub username: &'a str,
+    pub listing_control: &'a reddit::ListingControl,
+}
+
+pub(crate) struct GetSavedResult {
+    pub response: reddit::Object,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct GetSubredditTopParams<'a> {
+    pub access_token: &'a str,
+    pub subreddit: &'a str,
+    /// The time window to rank in, e.g. `day` or `week`.
+    pub time: &'a str,
+    pub listing_control: &'a reddit::ListingControl,
+}
+
+pub(crate) struct GetSubredditTopResult {
+    pub response: reddit::Object,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct RemoveParams<'a> {
+    pub access_token: &'a str,
+    /// The fullname of the thing to remove.
+    pub id: &'a str,
+}
+
+pub(crate) struct RemoveResult {}
+
+#[derive(Clone, Copy)]
+pub(crate) struct SetPrefsParams<'a> {
+    pub access_token: &'a str,
+    pub prefs: &'a reddit::Prefs,
+}
+
+pub(crate) struct SetPrefsResult {}
+
+#[derive(Clone)]
 pub(crate) struct SubmitParams<'a> {
     pub access_token: &'a str,
     pub post: reddit::Post,
+    /// Whether replies to the post go to the author's inbox. Reddit's
+    /// default is `true`.
+    pub send_replies: bool,
+}
+
+pub(crate) struct SubmitResult {
+    /// The fullname of the new post, when Reddit tells us.
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct UnsaveParams<'a> {
+    pub access_token: &'a str,
+    /// The fullname of the thing to unsave.
+    pub id: &'a str,
+}
+
+pub(crate) struct UnsaveResult {}
+
+fn media_mimetype(path: &Path) -> Result<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("mp4") => Ok("video/mp4"),
+        Some("mov") => Ok("video/quicktime"),
+        Some("jpg") | Some("jpeg") => Ok("image/jpeg"),
+        Some("png") => Ok("image/png"),
+        Some("gif") => Ok("image/gif"),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unsupported media file {}", path.display()),
+        )),
+    }
 }
 
-pub(crate) struct SubmitResult {}
+/// Checks the response of a submit and returns the fullname of the new post,
+/// if Reddit says. Besides the usual JSON body, Reddit may answer with a 202
+/// or a redirect pointing at the new post, which is a success too.
+async fn check_submit_response(
+    res: reqwest::Response,
+) -> Result<Option<String>> {
+    let status = res.status();
+
+    if status == reqwest::StatusCode::ACCEPTED || status.is_redirection() {
+        let location = res
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+
+        return match location.and_then(reddit::fullname_from_permalink) {
+            Some(name) => Ok(Some(name)),
+            None => Err(Error::new(
+                ErrorKind::Reddit,
+                format!("submit returned {} without a post location", status),
+            )),
+        };
+    }
+
+    let res = check_response::<reddit::SubmitResponse>(res).await?.json;
+
+    if !res.errors.is_empty() {
+        let errors: Vec<String> = res
+            .errors
+            .iter()
+            .map(|error| {
+                error
+                    .iter()
+                    .filter_map(|part| part.as_str())
+                    .collect::<Vec<_>>()
+                    .join(": ")
+            })
+            .collect();
+
+        return Err(Error::new(
+            ErrorKind::Reddit,
+            format!("submit unsuccessful: {}", errors.join("; ")),
+        ));
+    }
+
+    // Video submits are named later, once the video has been processed.
+    Ok(res.data.and_then(|data| data.name))
+}
 
 async fn check_response<T: serde::de::DeserializeOwned>(
     res: reqwest::Response,
 ) -> Result<T> {
     if res.status() != reqwest::StatusCode::OK {
+        let kind = if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            ErrorKind::Authentication
+        } else {
+            ErrorKind::Reddit
+        };
+
         log::error!(
             "Reqest returned bad status {}: {}",
             res.status(),
             res.text().await?
         );
 
-        return Err(Error::from(ErrorKind::Reddit));
+        return Err(Error::from(kind));
     }
 
     let text = res.text().await?;
@@ -318,3 +933,427 @@ async fn check_response<T: serde::de::DeserializeOwned>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Response, Server};
+
+    const SUBMITTED: &str = r#"{"json": {"errors": [], "data": {
+        "url": "https://www.reddit.com/r/rust/comments/abc123/a_title/",
+        "id": "abc123",
+        "name": "t3_abc123"
+    }}}"#;
+
+    fn client(server: &Server) -> ClientImpl {
+        ClientImpl::with_base_url(
+            Params {
+                user_agent: "test".to_string(),
+                writes_per_minute: 60,
+            },
+            &server.url(),
+        )
+    }
+
+    fn lease(server: &Server, key: &str) -> Response {
+        Response::ok(&format!(
+            r#"{{"args": {{"action": "{}/upload", "fields": [
+                {{"name": "acl", "value": "private"}},
+                {{"name": "key", "value": "{}"}}
+            ]}}}}"#,
+            server.url(),
+            key
+        ))
+    }
+
+    #[tokio::test]
+    async fn video_is_submitted_with_its_uploaded_thumbnail() {
+        let video = testing::temp_path("video.mp4");
+        let thumbnail = testing::temp_path("thumbnail.png");
+
+        std::fs::write(&video, b"video").unwrap();
+        std::fs::write(&thumbnail, b"thumbnail").unwrap();
+
+        let server = Server::new();
+
+        server.serve(vec![
+            lease(&server, "video-key"),
+            Response::ok(""),
+            lease(&server, "thumbnail-key"),
+            Response::ok(""),
+            Response::ok(
+                r#"{"json": {"errors": [], "data": {
+                    "user_submitted_page": "https://reddit.com/user/someone/",
+                    "websocket_url": "wss://ws.redditmedia.com/abc"
+                }}}"#,
+            ),
+        ]);
+
+        client(&server)
+            .submit(&SubmitParams {
+                access_token: "token",
+                post: reddit::Post::Video {
+                    subreddit: "rust".to_string(),
+                    title: "A video".to_string(),
+                    video_path: video.clone(),
+                    thumbnail_path: thumbnail.clone(),
+                },
+                send_replies: true,
+            })
+            .await
+            .unwrap();
+
+        std::fs::remove_file(video).unwrap();
+        std::fs::remove_file(thumbnail).unwrap();
+
+        let requests = server.requests();
+        let paths: Vec<&str> =
+            requests.iter().map(|req| req.path.as_str()).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "/api/media/asset.json",
+                "/upload",
+                "/api/media/asset.json",
+                "/upload",
+                "/api/submit",
+            ]
+        );
+
+        let video_lease = requests[0].form();
+        let thumbnail_lease = requests[2].form();
+
+        assert_eq!(video_lease["mimetype"], "video/mp4");
+        assert!(video_lease["filepath"].ends_with("video.mp4"));
+        assert_eq!(thumbnail_lease["mimetype"], "image/png");
+        assert!(thumbnail_lease["filepath"].ends_with("thumbnail.png"));
+
+        let upload = requests[1].text();
+
+        assert!(upload.contains("name=\"acl\"\r\n\r\nprivate\r\n"));
+        assert!(upload.contains("name=\"key\"\r\n\r\nvideo-key\r\n"));
+        assert!(upload.contains("\r\n\r\nvideo\r\n"));
+
+        let form = requests[4].form();
+
+        assert_eq!(requests[4].method, "POST");
+        assert_eq!(requests[4].headers["authorization"], "Bearer token");
+
+        assert_eq!(form["kind"], "video");
+        assert_eq!(form["sr"], "rust");
+        assert_eq!(form["url"], format!("{}/upload/video-key", server.url()));
+        assert_eq!(
+            form["video_poster_url"],
+            format!("{}/upload/thumbnail-key", server.url())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_app_credentials_explain_the_app_type() {
+        let server = Server::new();
+
+        server.serve(vec![Response {
+            status: 401,
+            headers: Vec::new(),
+            body: r#"{"message": "Unauthorized", "error": 401}"#.to_string(),
+        }]);
+
+        let err = client(&server)
+            .basic_auth(&BasicAuthParams {
+                credentials: &testing::settings(&[]).credentials,
+            })
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::Authentication);
+        assert!(err.to_string().contains("\"script\" app"));
+        assert_eq!(server.requests()[0].path, "/api/v1/access_token");
+    }
+
+    #[tokio::test]
+    async fn accepted_submit_is_named_by_its_location() {
+        let server = Server::new();
+
+        server.serve(vec![Response {
+            status: 202,
+            headers: vec![(
+                "Location",
+                "https://www.reddit.com/r/rust/comments/abc123/a_title/"
+                    .to_string(),
+            )],
+            body: String::new(),
+        }]);
+
+        let res = client(&server)
+            .submit(&SubmitParams {
+                access_token: "token",
+                post: reddit::Post::SelfPost {
+                    subreddit: "rust".to_string(),
+                    title: "A title".to_string(),
+                    body: reddit::SelfPostBody::Text("Text".to_string()),
+                },
+                send_replies: true,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.name.as_deref(), Some("t3_abc123"));
+    }
+
+    #[tokio::test]
+    async fn other_requests_follow_redirects() {
+        let server = Server::new();
+
+        server.serve(vec![
+            Response {
+                status: 302,
+                headers: vec![("Location", format!("{}/moved", server.url()))],
+                body: String::new(),
+            },
+            Response::ok(r#"{"over_18": true}"#),
+        ]);
+
+        let res = client(&server)
+            .get_prefs(&GetPrefsParams {
+                access_token: "token",
+            })
+            .await
+            .unwrap();
+
+        assert!(res.prefs.over_18);
+        assert_eq!(server.requests()[1].path, "/moved");
+    }
+
+    #[tokio::test]
+    async fn cloned_params_make_the_same_request() {
+        let server = Server::new();
+
+        server.serve(vec![Response::ok(SUBMITTED), Response::ok(SUBMITTED)]);
+
+        let params = SubmitParams {
+            access_token: "token",
+            post: reddit::Post::SelfPost {
+                subreddit: "rust".to_string(),
+                title: "A title".to_string(),
+                body: reddit::SelfPostBody::Text("Text".to_string()),
+            },
+            send_replies: false,
+        };
+        let client = client(&server);
+
+        client.submit(&params.clone()).await.unwrap();
+        client.submit(&params).await.unwrap();
+
+        let requests = server.requests();
+
+        assert_eq!(requests[0].method, requests[1].method);
+        assert_eq!(requests[0].path, requests[1].path);
+        assert_eq!(
+            requests[0].headers["authorization"],
+            requests[1].headers["authorization"]
+        );
+        assert_eq!(requests[0].form(), requests[1].form());
+        assert_eq!(requests[0].form()["title"], "A title");
+    }
+
+    #[tokio::test]
+    async fn submit_form_says_whether_to_send_replies() {
+        for &(send_replies, expected) in &[(true, "true"), (false, "false")] {
+            let server = Server::new();
+
+            server.serve(vec![Response::ok(SUBMITTED)]);
+
+            client(&server)
+                .submit(&SubmitParams {
+                    access_token: "token",
+                    post: reddit::Post::Link {
+                        subreddit: "rust".to_string(),
+                        title: "A link".to_string(),
+                        url: "https://www.rust-lang.org/".parse().unwrap(),
+                    },
+                    send_replies,
+                })
+                .await
+                .unwrap();
+
+            let form = server.requests()[0].form();
+
+            assert_eq!(form["kind"], "link");
+            assert_eq!(form["send_replies"], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_sent_again() {
+        let server = Server::new();
+        let too_many = || Response {
+            status: 429,
+            headers: Vec::new(),
+            body: String::new(),
+        };
+
+        server.serve(vec![
+            too_many(),
+            too_many(),
+            Response::ok(r#"{"over_18": true}"#),
+        ]);
+
+        let res = client(&server)
+            .get_prefs(&GetPrefsParams {
+                access_token: "token",
+            })
+            .await
+            .unwrap();
+
+        assert!(res.prefs.over_18);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn submit_is_named_by_the_response() {
+        let server = Server::new();
+
+        server.serve(vec![Response::ok(SUBMITTED)]);
+
+        let res = client(&server)
+            .submit(&SubmitParams {
+                access_token: "token",
+                post: reddit::Post::SelfPost {
+                    subreddit: "rust".to_string(),
+                    title: "A title".to_string(),
+                    body: reddit::SelfPostBody::Text("Text".to_string()),
+                },
+                send_replies: true,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.name.as_deref(), Some("t3_abc123"));
+        assert_eq!(server.requests()[0].form()["api_type"], "json");
+    }
+
+    #[tokio::test]
+    async fn submit_errors_are_reported() {
+        let server = Server::new();
+
+        server.serve(vec![Response::ok(
+            r#"{"json": {"errors": [
+                ["SUBREDDIT_NOEXIST", "that subreddit doesn't exist", "sr"]
+            ]}}"#,
+        )]);
+
+        let err = client(&server)
+            .submit(&SubmitParams {
+                access_token: "token",
+                post: reddit::Post::SelfPost {
+                    subreddit: "rust".to_string(),
+                    title: "A title".to_string(),
+                    body: reddit::SelfPostBody::Text("Text".to_string()),
+                },
+                send_replies: true,
+            })
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::Reddit);
+        assert_eq!(
+            err.to_string(),
+            "submit unsuccessful: SUBREDDIT_NOEXIST: that subreddit doesn't \
+            exist: sr"
+        );
+    }
+
+    #[tokio::test]
+    async fn self_post_form_has_the_text_as_is() {
+        let server = Server::new();
+        let text = "Cafe\u{301} and re\u{301}sume\u{301} 🦀\nSecond line";
+
+        server.serve(vec![Response::ok(SUBMITTED)]);
+
+        client(&server)
+            .submit(&SubmitParams {
+                access_token: "token",
+                post: reddit::Post::SelfPost {
+                    subreddit: "rust".to_string(),
+                    title: "Rust 🦀 release".to_string(),
+                    body: reddit::SelfPostBody::Text(text.to_string()),
+                },
+                send_replies: true,
+            })
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        let mut form: Vec<(String, String)> =
+            requests[0].form().into_iter().collect();
+
+        form.sort();
+
+        assert_eq!(requests[0].path, "/api/submit");
+        assert_eq!(
+            requests[0].headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(
+            form,
+            vec![
+                ("api_type".to_string(), "json".to_string()),
+                ("kind".to_string(), "self".to_string()),
+                ("resubmit".to_string(), "true".to_string()),
+                ("send_replies".to_string(), "true".to_string()),
+                ("sr".to_string(), "rust".to_string()),
+                ("text".to_string(), text.to_string()),
+                ("title".to_string(), "Rust 🦀 release".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn approve_and_remove_forms_name_the_thing() {
+        let server = Server::new();
+        let client = client(&server);
+
+        server.serve(vec![Response::ok("{}"), Response::ok("{}")]);
+        client
+            .approve(&ApproveParams {
+                access_token: "token",
+                id: "t1_abc",
+            })
+            .await
+            .unwrap();
+        client
+            .remove(&RemoveParams {
+                access_token: "token",
+                id: "t3_def",
+            })
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        let forms: Vec<Vec<(String, String)>> = requests
+            .iter()
+            .map(|request| {
+                let mut form: Vec<_> = request.form().into_iter().collect();
+
+                form.sort();
+                form
+            })
+            .collect();
+
+        assert_eq!(requests[0].path, "/api/approve");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+        assert_eq!(forms[0], vec![("id".to_string(), "t1_abc".to_string())]);
+        assert_eq!(requests[1].path, "/api/remove");
+        assert_eq!(requests[1].headers["authorization"], "Bearer token");
+        assert_eq!(
+            forms[1],
+            vec![
+                ("id".to_string(), "t3_def".to_string()),
+                ("spam".to_string(), "false".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Compares two snapshots taken by `scan`.
+
+use std::collections::BTreeMap;
+
+use crate::app::Snapshot;
+use crate::reddit;
+
+/// What changed between two snapshots. Items are identified by fullname and
+/// listed in fullname order.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct SnapshotDiff {
+    /// Items only in the new snapshot.
+    pub added: Vec<String>,
+    /// Items only in the old snapshot.
+    pub removed: Vec<String>,
+    /// Items in both snapshots whose score or text differ.
+    pub changed: Vec<Change>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Change {
+    pub name: String,
+    /// The old and new scores, if the score changed.
+    pub score: Option<(i64, i64)>,
+    /// Whether the comment's body or the post's self text changed.
+    pub text_changed: bool,
+}
+
+/// Compares the comments and posts of `old` and `new`. Saved items are left
+/// out, since they aren't the user's own.
+pub(crate) fn diff(old: &Snapshot, new: &Snapshot) -> SnapshotDiff {
+    let old = by_name(old);
+    let new = by_name(new);
+    let mut diff = SnapshotDiff::default();
+
+    for (name, old_item) in &old {
+        match new.get(name) {
+            None => diff.removed.push(name.to_string()),
+            Some(new_item) => {
+                let score = match (old_item.score(), new_item.score()) {
+                    (Some(old), Some(new)) if old != new => Some((old, new)),
+                    _ => None,
+                };
+                let text_changed = old_item.text() != new_item.text();
+
+                if score.is_some() || text_changed {
+                    diff.changed.push(Change {
+                        name: name.to_string(),
+                        score,
+                        text_changed,
+                    });
+                }
+            }
+        }
+    }
+
+    diff.added = new
+        .keys()
+        .filter(|name| !old.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+
+    diff
+}
+
+fn by_name(snapshot: &Snapshot) -> BTreeMap<&str, &reddit::Object> {
+    snapshot
+        .comments
+        .iter()
+        .chain(&snapshot.posts)
+        .filter_map(|item| item.name().map(|name| (name, item)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn edited(mut item: reddit::Object) -> reddit::Object {
+        if let reddit::Object::Link {
+            ref mut selftext, ..
+        } = item
+        {
+            selftext.push_str(" (edited)");
+        }
+
+        item
+    }
+
+    #[test]
+    fn diff_lists_added_removed_and_changed_items() {
+        let old = Snapshot {
+            comments: vec![
+                testing::comment("kept", "rust"),
+                testing::comment("gone", "rust"),
+                testing::scored(testing::comment("voted", "rust"), 1),
+            ],
+            posts: vec![testing::post("edited", "rust")],
+            saved: vec![testing::post("saved", "pics")],
+        };
+        let new = Snapshot {
+            comments: vec![
+                testing::comment("kept", "rust"),
+                testing::comment("new", "rust"),
+                testing::scored(testing::comment("voted", "rust"), 10),
+            ],
+            posts: vec![edited(testing::post("edited", "rust"))],
+            saved: Vec::new(),
+        };
+
+        let diff = diff(&old, &new);
+
+        assert_eq!(diff.added, vec!["t1_new"]);
+        assert_eq!(diff.removed, vec!["t1_gone"]);
+
+        let changed: Vec<_> = diff
+            .changed
+            .iter()
+            .map(|change| {
+                (change.name.as_str(), change.score, change.text_changed)
+            })
+            .collect();
+
+        assert_eq!(
+            changed,
+            vec![
+                ("t1_voted", Some((1, 10)), false),
+                ("t3_edited", None, true)
+            ]
+        );
+    }
+}
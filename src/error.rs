@@ -43,6 +43,15 @@ impl Error {
     }
 }
 
+impl Error {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self.repr {
+            Repr::Simple(kind) => kind,
+            Repr::Custom(ref c) => c.kind,
+        }
+    }
+}
+
 impl ErrorKind {
     pub(crate) fn as_str(self) -> &'static str {
         match self {
@@ -0,0 +1,296 @@
+//! Keeps overlapping runs, e.g. from cron jobs, off the same account.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// An exclusive lock on a run, held for as long as the value lives. The lock
+/// file is removed on drop, which also happens when unwinding from a panic.
+#[derive(Debug)]
+pub(crate) struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Takes the lock at `path`, failing if another run holds it or, with a
+    /// `min_interval`, if the last recorded run finished less than that long
+    /// ago.
+    pub(crate) fn acquire(
+        path: &Path,
+        min_interval: Option<Duration>,
+    ) -> Result<Lock> {
+        let mut file = match create(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                match stale_pid(path) {
+                    Some(pid) => take_over(path, pid)?,
+                    None => return Err(already_running(path)),
+                }
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let lock = Lock {
+            path: path.to_path_buf(),
+        };
+
+        writeln!(file, "{}", process::id())?;
+
+        if let Some(min_interval) = min_interval {
+            let elapsed = fs::metadata(last_run_path(path))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok());
+
+            if let Some(elapsed) = elapsed.filter(|e| *e < min_interval) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the last run finished {}s ago, less than the minimum \
+                        interval of {}s",
+                        elapsed.as_secs(),
+                        min_interval.as_secs()
+                    ),
+                ));
+            }
+        }
+
+        Ok(lock)
+    }
+
+    /// Records that a run finished now, for `min_interval`. Only runs that
+    /// deleted are recorded, so a dry run or a failed one doesn't hold off the
+    /// next.
+    pub(crate) fn record_run(&self) -> Result<()> {
+        fs::write(last_run_path(&self.path), format!("{}\n", process::id()))?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!(
+                "Failed to remove lock file {}: {}.",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Creates the lock file, failing if it exists.
+fn create(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+/// Takes over the lock file at `path` left behind by `pid`, which is no longer
+/// going. The stale file is renamed to a name only this process uses before a
+/// new one is created, so of several runs taking over at once only one gets
+/// it.
+fn take_over(path: &Path, pid: u32) -> Result<File> {
+    let claimed = sibling(path, &format!(".stale-{}", process::id()));
+
+    match fs::rename(path, &claimed) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(already_running(path))
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    if read_pid(&claimed) != Some(pid) {
+        // Another run took it over since we looked, so this is its lock. Put
+        // it back unless yet another run has created one meanwhile.
+        let res = fs::hard_link(&claimed, path);
+
+        fs::remove_file(&claimed)?;
+
+        match res {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+                return Err(err.into())
+            }
+            _ => return Err(already_running(path)),
+        }
+    }
+
+    log::warn!(
+        "Removing lock file {} left behind by a run that is no longer going.",
+        path.display()
+    );
+    fs::remove_file(&claimed)?;
+
+    create(path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => already_running(path),
+        _ => err.into(),
+    })
+}
+
+fn already_running(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::Io,
+        format!(
+            "regreddit is already running: {} exists. Delete it if no other \
+            run is going on",
+            path.display()
+        ),
+    )
+}
+
+/// The PID recorded in the lock file at `path`.
+fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+}
+
+/// The PID recorded in the lock file at `path` if it is of a process that no
+/// longer exists. Only known where there is a `/proc`; elsewhere a lock is
+/// always taken to be held.
+fn stale_pid(path: &Path) -> Option<u32> {
+    read_pid(path).filter(|pid| {
+        Path::new("/proc/self").exists()
+            && !Path::new("/proc").join(pid.to_string()).exists()
+    })
+}
+
+/// The file whose modification time records when the last run finished.
+fn last_run_path(lock_path: &Path) -> PathBuf {
+    sibling(lock_path, ".last-run")
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// A length of time such as `90`, `30s`, `15m`, `6h` or `1d`. A bare number is
+/// in seconds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => s.split_at(i),
+            None => (s, "s"),
+        };
+        let number: u64 = number
+            .parse()
+            .map_err(|err| format!("invalid interval {}: {}", s, err))?;
+        let secs = match unit {
+            "s" => number,
+            "m" => number * 60,
+            "h" => number * 60 * 60,
+            "d" => number * 60 * 60 * 24,
+            _ => return Err(format!("unknown unit in interval {}", s)),
+        };
+
+        Ok(Interval(Duration::from_secs(secs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn held_lock_reports_that_regreddit_is_already_running() {
+        let path = testing::temp_path("held.lock");
+        let lock = Lock::acquire(&path, None).unwrap();
+
+        let err = Lock::acquire(&path, None).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "regreddit is already running: {} exists. Delete it if no \
+                other run is going on",
+                path.display()
+            )
+        );
+
+        drop(lock);
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn lock_of_a_dead_process_is_taken_over() {
+        let path = testing::temp_path("stale.lock");
+        let mut child = process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+
+        child.wait().unwrap();
+        fs::write(&path, format!("{}\n", dead_pid)).unwrap();
+
+        let lock = Lock::acquire(&path, None).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        drop(lock);
+    }
+
+    #[test]
+    fn lock_without_a_pid_is_held() {
+        let path = testing::temp_path("unreadable.lock");
+
+        fs::write(&path, "").unwrap();
+
+        assert!(Lock::acquire(&path, None).is_err());
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lock_taken_over_by_another_run_is_left_alone() {
+        let path = testing::temp_path("taken-over.lock");
+
+        fs::write(&path, format!("{}\n", process::id())).unwrap();
+
+        // As if the lock had still been stale when this run looked at it.
+        let err = take_over(&path, u32::MAX).unwrap_err();
+
+        assert!(err.to_string().contains("already running"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+        assert!(!sibling(&path, &format!(".stale-{}", process::id())).exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_recorded_runs_count_towards_the_min_interval() {
+        let path = testing::temp_path("interval.lock");
+        let hour = Some(Duration::from_secs(60 * 60));
+
+        drop(Lock::acquire(&path, hour).unwrap());
+
+        let lock = Lock::acquire(&path, hour).unwrap();
+
+        lock.record_run().unwrap();
+        drop(lock);
+
+        let err = Lock::acquire(&path, hour).unwrap_err();
+
+        assert!(err.to_string().contains("minimum interval of 3600s"));
+        fs::remove_file(last_run_path(&path)).unwrap();
+    }
+}
@@ -1,44 +1,879 @@
 mod app;
+mod backoff;
+#[cfg(feature = "blocking")]
+mod blocking;
 mod client;
+mod diff;
 mod error;
+mod lock;
+mod notify;
+mod output;
+mod rate_limit;
 mod reddit;
+mod session;
 mod settings;
+mod state;
+#[cfg(test)]
+mod testing;
 
 #[macro_use]
 extern crate serde_derive;
 
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::num::NonZeroU32;
+use std::path::Path;
 use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::app::{App, AppImpl, Params, RegredditParams};
 use crate::client::ClientImpl;
+use crate::lock::Lock;
 use crate::settings::Settings;
+use crate::state::State;
 
 static NAME: &str = "regreddit";
 static VERSION: &str = "v0.2.0";
 static AUTHOR_REDDIT_USERNAME: &str = "trustyhardware";
+static SUBREDDIT_HELP: &str =
+    "The subreddit to submit to. Separate several subreddits with commas.";
 
+// `App` also works on the basic scheduler; the threaded one just lets the
+// delete requests run in parallel.
 #[tokio::main]
 async fn main() {
-    let matches = clap::App::new("regreddit")
+    let matches = cli().get_matches();
+
+    config_logger(matches.occurrences_of("verbosity"));
+
+    if matches.subcommand_matches("verify-config").is_some() {
+        verify_config();
+    }
+
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        diff_snapshots(
+            diff_matches.value_of("old").unwrap(),
+            diff_matches.value_of("new").unwrap(),
+            parse_value_of(&matches, "output"),
+        );
+    }
+
+    let client = ClientImpl::new(client::Params {
+        user_agent: format!(
+            "{}/{} by /u/{}",
+            NAME, VERSION, AUTHOR_REDDIT_USERNAME
+        ),
+        writes_per_minute: parse_value_of::<NonZeroU32>(&matches, "rate").get(),
+    });
+    let app = AppImpl::new(Params {
+        client: Box::new(client),
+    });
+    let settings = match Settings::new() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Failed to read settings: {}.", err);
+            process::exit(1);
+        }
+    };
+
+    if let Some(matches) = matches.subcommand_matches("submit") {
+        if let Some(matches) = matches.subcommand_matches("link") {
+            match app
+                .submit_link(&app::SubmitLinkParams {
+                    credentials: &settings.credentials,
+                    subreddits: &subreddits_of(matches),
+                    title: matches.value_of("title").unwrap(),
+                    url: matches.value_of("url").unwrap(),
+                    send_replies: !matches.is_present("no-inbox-replies"),
+                })
+                .await
+            {
+                Ok(res) => process::exit(report_submissions(&res.submissions)),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1)
+                }
+            }
+        }
+
+        if let Some(matches) = matches.subcommand_matches("self-post") {
+            match app
+                .submit_self_post(&app::SubmitSelfPostParams {
+                    credentials: &settings.credentials,
+                    subreddits: &subreddits_of(matches),
+                    title: matches.value_of("title").unwrap(),
+                    text: matches.value_of("text"),
+                    text_file: matches.value_of("text-file"),
+                    richtext_json: matches.value_of("richtext-json"),
+                    richtext_json_file: matches.value_of("richtext-json-file"),
+                    send_replies: !matches.is_present("no-inbox-replies"),
+                })
+                .await
+            {
+                Ok(res) => process::exit(report_submissions(&res.submissions)),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1)
+                }
+            }
+        }
+
+        if let Some(matches) = matches.subcommand_matches("video") {
+            match app
+                .submit_video(&app::SubmitVideoParams {
+                    credentials: &settings.credentials,
+                    subreddits: &subreddits_of(matches),
+                    title: matches.value_of("title").unwrap(),
+                    video: matches.value_of("video").unwrap(),
+                    thumbnail: matches.value_of("thumbnail").unwrap(),
+                    send_replies: !matches.is_present("no-inbox-replies"),
+                })
+                .await
+            {
+                Ok(res) => process::exit(report_submissions(&res.submissions)),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1)
+                }
+            }
+        }
+    }
+
+    if let Some(refresh_matches) = matches.subcommand_matches("refresh") {
+        match app
+            .refresh(&app::RefreshParams {
+                credentials: &settings.credentials,
+                id: refresh_matches.value_of("id").unwrap(),
+                allow_repost: refresh_matches.is_present("allow-repost"),
+                send_replies: !refresh_matches.is_present("no-inbox-replies"),
+                backup: refresh_matches.value_of("backup").map(Path::new),
+            })
+            .await
+        {
+            Ok(res) => {
+                match res.name {
+                    Some(name) => println!("{}", name),
+                    None => eprintln!(
+                        "Submitted again, but Reddit didn't say \
+                        under which name."
+                    ),
+                }
+                process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(restore_matches) = matches.subcommand_matches("restore-prefs") {
+        let backup = restore_matches.value_of("backup").unwrap();
+        let prefs = match read_prefs(backup) {
+            Ok(prefs) => prefs,
+            Err(err) => {
+                eprintln!("Failed to read backup {}: {}.", backup, err);
+                process::exit(1);
+            }
+        };
+
+        match app
+            .restore_prefs(&app::RestorePrefsParams {
+                credentials: &settings.credentials,
+                prefs: &prefs,
+            })
+            .await
+        {
+            Ok(_) => {
+                eprintln!("Restored preferences from {}.", backup);
+                process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(scan_matches) = matches.subcommand_matches("scan") {
+        let out = scan_matches.value_of("out").unwrap();
+
+        match app
+            .scan(&regreddit_params_of(&matches, &settings, None, None))
+            .await
+        {
+            Ok(res) => {
+                print_warnings(&res.warnings);
+
+                if let Err(err) = write_snapshot(out, &res.snapshot) {
+                    eprintln!("Failed to write snapshot {}: {}.", out, err);
+                    process::exit(1);
+                }
+
+                eprintln!(
+                    "Saved {} comments and {} posts to {}.",
+                    res.snapshot.comments.len(),
+                    res.snapshot.posts.len(),
+                    out
+                );
+                process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    process::exit(nuke(&app, &settings, &matches).await);
+}
+
+/// Deletes everything as `matches` says, from a snapshot with the `nuke`
+/// subcommand, and returns the exit code.
+async fn nuke(
+    app: &dyn App,
+    settings: &Settings,
+    matches: &clap::ArgMatches<'_>,
+) -> i32 {
+    let snapshot = match matches.subcommand_matches("nuke") {
+        Some(nuke_matches) => {
+            let from = nuke_matches.value_of("from").unwrap();
+
+            match read_snapshot(from) {
+                Ok(snapshot) => Some(snapshot),
+                Err(err) => {
+                    eprintln!("Failed to read snapshot {}: {}.", from, err);
+                    return 1;
+                }
+            }
+        }
+        None => None,
+    };
+
+    if matches.is_present("require-whitelist") {
+        if let Err(err) = require_whitelist(settings) {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    if matches.is_present("ignore-whitelist")
+        && !(settings.comment_whitelist().is_empty()
+            && settings.post_whitelist().is_empty())
+        && !confirm(
+            "--ignore-whitelist is set. Items in whitelisted subreddits will \
+            be deleted too. Continue?",
+        )
+    {
+        eprintln!("Exiting...");
+        return 1;
+    }
+
+    let notify_webhook: Option<url::Url> =
+        parse_optional_value_of(matches, "notify-webhook");
+    let plan_items = output::PlanItems {
+        context: matches.is_present("comment-context"),
+        permalinks: matches.is_present("permalink-output"),
+    };
+    let preview = |items: &[reddit::Object]| {
+        let mut stderr = io::stderr();
+        let res = output::write_plan(items, &mut stderr).and_then(|_| {
+            output::write_plan_items(items, plan_items, &mut stderr)
+        });
+
+        if let Err(err) = res {
+            eprintln!("Failed to write the preview: {}.", err);
+            return false;
+        }
+
+        confirm("Delete these items?")
+    };
+    let dump_plan_then_exit = matches.is_present("dump-plan-then-exit");
+    let planned = AtomicUsize::new(0);
+    let dump_plan = |items: &[reddit::Object]| {
+        let mut stdout = io::stdout();
+        let res = output::write_plan(items, &mut stdout).and_then(|_| {
+            output::write_plan_items(items, plan_items, &mut stdout)
+        });
+
+        if let Err(err) = res {
+            eprintln!("Failed to write the plan: {}.", err);
+        }
+
+        planned.store(items.len(), Ordering::SeqCst);
+
+        false
+    };
+    let confirm: Option<&(dyn Fn(&[reddit::Object]) -> bool + Sync)> =
+        if dump_plan_then_exit {
+            Some(&dump_plan)
+        } else if matches.is_present("yes") {
+            None
+        } else {
+            Some(&preview)
+        };
+
+    let state_file = matches.value_of("state-file").map(Path::new);
+    let mut state = match state_file.map(State::load).transpose() {
+        Ok(state) => state.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("Failed to read the state file: {}.", err);
+            return 1;
+        }
+    };
+    let mut params =
+        regreddit_params_of(matches, settings, confirm, snapshot.as_ref());
+
+    if matches.is_present("since-last-run") {
+        match state.last_run {
+            Some(last_run) => params.created_after = Some(last_run),
+            None => log::info!("No previous run. Considering everything."),
+        }
+    }
+
+    let output_format: output::Format = parse_value_of(matches, "output");
+    let max_allowed = parse_optional_value_of(matches, "max-allowed");
+    let exceeded_exit_code = parse_value_of(matches, "exceeded-exit-code");
+    let min_interval: Option<lock::Interval> =
+        parse_optional_value_of(matches, "min-interval");
+    // Taken last, since the parsing above may exit the process, which skips
+    // the destructor that removes it.
+    let lock = match matches.value_of("lock") {
+        Some(path) => {
+            match Lock::acquire(Path::new(path), min_interval.map(|i| i.0)) {
+                Ok(lock) => Some(lock),
+                Err(err) => {
+                    eprintln!("Not running: {}.", err);
+                    return 1;
+                }
+            }
+        }
+        None => None,
+    };
+
+    match app.regreddit(&params).await {
+        Ok(_) if dump_plan_then_exit => plan_exit_code(
+            planned.load(Ordering::SeqCst),
+            max_allowed,
+            exceeded_exit_code,
+        ),
+        Ok(res) if res.cancelled => {
+            eprintln!("Nothing was deleted.");
+            0
+        }
+        Ok(res) => {
+            print_warnings(&res.warnings);
+
+            let run = output::RunInfo {
+                version: VERSION,
+                filters: output::Filters::of(&params),
+            };
+
+            if let Err(err) = output::write_report(
+                output_format,
+                &run,
+                &res,
+                &mut io::stdout(),
+            ) {
+                eprintln!("Failed to write the summary: {}.", err);
+            }
+
+            eprintln!("Successfully nuked your Reddit account.");
+
+            if let Some(Err(err)) = lock.as_ref().map(Lock::record_run) {
+                eprintln!("Failed to record the run: {}.", err);
+            }
+
+            // Items that failed to delete are older than the run, so moving
+            // `last_run` past them would leave them behind for good.
+            if let Some(path) = state_file {
+                if res.failures().next().is_none() {
+                    state.last_run = Some(res.started_at);
+
+                    if let Err(err) = state.save(path) {
+                        eprintln!("Failed to write the state file: {}.", err);
+                    }
+                } else {
+                    eprintln!(
+                        "Some items failed to delete. Not updating the last \
+                        run, so the next run tries them again."
+                    );
+                }
+            }
+
+            let notification =
+                notify::Notification::Succeeded(output::JsonSummary {
+                    run: &run,
+                    report: &res,
+                });
+
+            send_notifications(matches, notify_webhook.as_ref(), &notification)
+                .await;
+
+            0
+        }
+        Err(err) => {
+            eprintln!("Error {:?}", err);
+
+            let run = output::RunInfo {
+                version: VERSION,
+                filters: output::Filters::of(&params),
+            };
+            let notification = notify::Notification::Failed {
+                run: &run,
+                error: err.to_string(),
+            };
+
+            send_notifications(matches, notify_webhook.as_ref(), &notification)
+                .await;
+
+            1
+        }
+    }
+}
+
+/// Sends `notification` to the `--notify-command` and `--notify-webhook`, if
+/// any. Failing to is only a warning, since the run itself is over.
+async fn send_notifications(
+    matches: &clap::ArgMatches<'_>,
+    webhook: Option<&url::Url>,
+    notification: &notify::Notification<'_>,
+) {
+    if let Some(command) = matches.value_of("notify-command") {
+        if let Err(err) = notify::run_command(command, notification).await {
+            eprintln!("Warning: failed to run notify command: {}.", err);
+        }
+    }
+
+    if let Some(url) = webhook {
+        if let Err(err) = notify::post_webhook(url, notification).await {
+            eprintln!("Warning: failed to notify {}: {}.", url, err);
+        }
+    }
+}
+
+/// Fails unless some subreddit is whitelisted, for `--require-whitelist`.
+fn require_whitelist(settings: &Settings) -> Result<(), &'static str> {
+    if settings.comment_whitelist().is_empty()
+        && settings.post_whitelist().is_empty()
+    {
+        return Err("--require-whitelist is set, but no subreddits are \
+            whitelisted. Check the whitelist keys in the config file.");
+    }
+
+    Ok(())
+}
+
+/// The exit code of `--dump-plan-then-exit` when `planned` items would be
+/// deleted.
+fn plan_exit_code(
+    planned: usize,
+    max_allowed: Option<usize>,
+    exceeded_exit_code: i32,
+) -> i32 {
+    match max_allowed {
+        Some(max_allowed) if planned > max_allowed => {
+            eprintln!(
+                "{} items would be deleted, more than the {} allowed.",
+                planned, max_allowed
+            );
+            exceeded_exit_code
+        }
+        _ => 0,
+    }
+}
+
+fn cli() -> clap::App<'static, 'static> {
+    clap::App::new("regreddit")
         .version(VERSION)
         .about("Nuke your Reddit account.")
         .author("Yage Hu <yagehu@qq.com>")
-        .arg(
-            clap::Arg::with_name("yes")
-                .long("yes")
-                .help("The command won't do anything without this flag."),
-        )
+        .arg(clap::Arg::with_name("yes").long("yes").help(
+            "Delete without previewing what will be deleted and \
+                asking first.",
+        ))
         .arg(
             clap::Arg::with_name("username")
                 .long("username")
                 .help("The username of the Reddit account."),
         )
+        .arg(
+            clap::Arg::with_name("listing-retries")
+                .long("listing-retries")
+                .help("How many times to retry fetching a page of a listing.")
+                .takes_value(true)
+                .default_value("5"),
+        )
+        .arg(
+            clap::Arg::with_name("delete-retries")
+                .long("delete-retries")
+                .help("How many times to retry deleting a single item.")
+                .takes_value(true)
+                .default_value("2"),
+        )
+        .arg(
+            clap::Arg::with_name("concurrency")
+                .long("concurrency")
+                .help(
+                    "How many items to delete at a time. `auto` or 0 derives \
+                it from Reddit's rate limit, `unlimited` removes the limit.",
+                )
+                .takes_value(true)
+                .default_value("auto"),
+        )
+        .arg(
+            clap::Arg::with_name("max-listing-pages")
+                .long("max-listing-pages")
+                .help("Stop scanning each listing after this many pages.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("listing-param")
+                .long("listing-param")
+                .help(
+                    "An extra query parameter to send with every listing \
+                request, as `key=value`. Can be given more than once.",
+                )
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("thorough").long("thorough").help(
+            "Also look up comments hidden behind \"load more\" stubs. \
+                This costs extra requests.",
+        ))
+        .arg(
+            clap::Arg::with_name("sample")
+                .long("sample")
+                .help(
+                    "Only delete a random sample of this many items, to try \
+                things out before a full run.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("seed")
+                .long("seed")
+                .help("The seed for anything random, for reproducible runs.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("randomize-order")
+                .long("randomize-order")
+                .help(
+                    "Delete items in random order instead of newest first, \
+                so the deletions look less automated. The whole list is \
+                shuffled at once, not page by page.",
+                ),
+        )
+        .arg(clap::Arg::with_name("set-over18").long("set-over18").help(
+            "Temporarily enable the account's over_18 preference so \
+                NSFW content is not skipped.",
+        ))
+        .arg(
+            clap::Arg::with_name("ignore-whitelist")
+                .long("ignore-whitelist")
+                .help("Delete items in whitelisted subreddits too."),
+        )
+        .arg(
+            clap::Arg::with_name("require-whitelist")
+                .long("require-whitelist")
+                .help("Abort if no subreddits are whitelisted.")
+                .conflicts_with("ignore-whitelist"),
+        )
+        .arg(
+            clap::Arg::with_name("strict-subreddit-match")
+                .long("strict-subreddit-match")
+                .help("Match whitelisted subreddit names case-sensitively."),
+        )
+        .arg(
+            clap::Arg::with_name("only-inaccessible")
+                .long("only-inaccessible")
+                .help(
+                    "Only delete items in banned, private or quarantined \
+                subreddits.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("only-top-level")
+                .long("only-top-level")
+                .help(
+                    "Only delete comments replying to a post, keeping \
+                replies to other comments. Posts are unaffected.",
+                )
+                .conflicts_with("only-replies"),
+        )
+        .arg(
+            clap::Arg::with_name("only-replies")
+                .long("only-replies")
+                .help(
+                    "Only delete comments replying to other comments, \
+                keeping top-level comments. Posts are unaffected.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("score-min")
+                .long("score-min")
+                .help("Only delete items with at least this score.")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            clap::Arg::with_name("score-max")
+                .long("score-max")
+                .help("Only delete items with at most this score.")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            clap::Arg::with_name("keep-if-trending")
+                .long("keep-if-trending")
+                .help(
+                    "Keep posts that are among today's top posts in their \
+                subreddit. Costs a request per subreddit.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("mod-action")
+                .long("mod-action")
+                .help(
+                    "In subreddits you moderate, approve or remove each item \
+                before deleting it.",
+                )
+                .takes_value(true)
+                .possible_values(&["approve", "remove"]),
+        )
+        .arg(
+            clap::Arg::with_name("unsave")
+                .long("unsave")
+                .help("Also unsave every saved item."),
+        )
+        .arg(
+            clap::Arg::with_name("unsave-others-only")
+                .long("unsave-others-only")
+                .help(
+                    "Only unsave items by other users, keeping your own \
+                saved.",
+                )
+                .requires("unsave"),
+        )
+        .arg(
+            clap::Arg::with_name("comment-context")
+                .long("comment-context")
+                .help(
+                    "List every item in the preview, with the title of the \
+                post each comment is on.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("dump-plan-then-exit")
+                .long("dump-plan-then-exit")
+                .help(
+                    "Print a summary of what would be deleted and exit \
+                without deleting anything.",
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("max-allowed")
+                .long("max-allowed")
+                .help(
+                    "With --dump-plan-then-exit, fail if more than this many \
+                items would be deleted.",
+                )
+                .takes_value(true)
+                .requires("dump-plan-then-exit"),
+        )
+        .arg(
+            clap::Arg::with_name("exceeded-exit-code")
+                .long("exceeded-exit-code")
+                .help("The exit code when --max-allowed is exceeded.")
+                .takes_value(true)
+                .default_value("2"),
+        )
+        .arg(
+            clap::Arg::with_name("permalink-output")
+                .long("permalink-output")
+                .help("List the permalink of every item in the preview."),
+        )
+        .arg(
+            clap::Arg::with_name("notify-command")
+                .long("notify-command")
+                .help(
+                    "A shell command to run after a run finishes. It gets the \
+                JSON summary on stdin.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("notify-webhook")
+                .long("notify-webhook")
+                .help("A URL to POST the JSON summary to after a run finishes.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("backup-prefs")
+                .long("backup-prefs")
+                .help(
+                    "Save the account's preferences to this file before \
+                changing any. Restore them with `restore-prefs`.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("state-file")
+                .long("state-file")
+                .help("A file to remember when the last run was in.")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("since-last-run")
+                .long("since-last-run")
+                .help(
+                    "Only delete items created since the last successful run \
+                recorded in the state file.",
+                )
+                .requires("state-file"),
+        )
+        .arg(
+            clap::Arg::with_name("lock")
+                .long("lock")
+                .help(
+                    "A lock file that keeps two runs from overlapping, e.g. \
+                when run from cron.",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("min-interval")
+                .long("min-interval")
+                .help(
+                    "Don't run if the last run holding the lock that \
+                deleted finished less than this long ago, e.g. `30m`, `6h` \
+                or `1d`.",
+                )
+                .takes_value(true)
+                .requires("lock"),
+        )
+        .arg(
+            clap::Arg::with_name("output")
+                .long("output")
+                .help("The format of the run summary.")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv"])
+                .default_value("text"),
+        )
+        .arg(
+            clap::Arg::with_name("max-auth-failures")
+                .long("max-auth-failures")
+                .help(
+                    "Abort after this many consecutive failed attempts to \
+                authenticate again.",
+                )
+                .takes_value(true)
+                .default_value("3"),
+        )
+        .arg(
+            clap::Arg::with_name("rate")
+                .long("rate")
+                .help("How many deletes and submits to make per minute.")
+                .takes_value(true)
+                .default_value("60"),
+        )
         .arg(
             clap::Arg::with_name("verbosity")
                 .short("v")
                 .help("The verbosity of logging. Can be repeated `-vvv`")
                 .multiple(true),
         )
+        .subcommand(clap::SubCommand::with_name("verify-config").about(
+            "Check the config file for mistakes without contacting Reddit.",
+        ))
+        .subcommand(
+            clap::SubCommand::with_name("scan")
+                .about(
+                    "Save every comment and post to a snapshot file without \
+                deleting anything.",
+                )
+                .arg(
+                    clap::Arg::with_name("out")
+                        .long("out")
+                        .help("The snapshot file to write.")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("refresh")
+                .about(
+                    "Delete a post and submit it again, resetting its age and \
+                score.",
+                )
+                .arg(no_inbox_replies_arg())
+                .arg(
+                    clap::Arg::with_name("id")
+                        .help("The fullname of the post, e.g. t3_abc123.")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("allow-repost")
+                        .long("allow-repost")
+                        .help(
+                            "Refresh link posts too, which Reddit may flag \
+                        as reposts.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("backup")
+                        .long("backup")
+                        .help(
+                            "Save the post to this file before deleting it, \
+                        in case submitting it again fails.",
+                        )
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore-prefs")
+                .about("Restore preferences saved by `--backup-prefs`.")
+                .arg(
+                    clap::Arg::with_name("backup")
+                        .help("The backup file.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("diff")
+                .about(
+                    "Show which items appeared, disappeared or changed \
+                between two snapshot files.",
+                )
+                .arg(
+                    clap::Arg::with_name("old")
+                        .help("The older snapshot file.")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("new")
+                        .help("The newer snapshot file.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("nuke")
+                .about(
+                    "Delete the comments and posts in a snapshot file instead \
+                of the current ones.",
+                )
+                .arg(
+                    clap::Arg::with_name("from")
+                        .long("from")
+                        .help("The snapshot file written by `scan`.")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
         .subcommand(
             clap::SubCommand::with_name("submit")
                 .about("Submit to Reddit.")
@@ -46,7 +881,13 @@ async fn main() {
                 .subcommand(
                     clap::SubCommand::with_name("link")
                         .about("Submit a link.")
-                        .arg(clap::Arg::with_name("subreddit").required(true))
+                        .arg(no_inbox_replies_arg())
+                        .arg(
+                            clap::Arg::with_name("subreddit")
+                                .help(SUBREDDIT_HELP)
+                                .required(true)
+                                .use_delimiter(true),
+                        )
                         .arg(clap::Arg::with_name("title").required(true))
                         .arg(
                             clap::Arg::with_name("url")
@@ -57,7 +898,13 @@ async fn main() {
                 .subcommand(
                     clap::SubCommand::with_name("self-post")
                         .about("Submit a self-post.")
-                        .arg(clap::Arg::with_name("subreddit").required(true))
+                        .arg(no_inbox_replies_arg())
+                        .arg(
+                            clap::Arg::with_name("subreddit")
+                                .help(SUBREDDIT_HELP)
+                                .required(true)
+                                .use_delimiter(true),
+                        )
                         .arg(clap::Arg::with_name("title").required(true))
                         .group(
                             clap::ArgGroup::with_name("content")
@@ -80,7 +927,7 @@ async fn main() {
                                 .long("text-file")
                                 .help(
                                     "A file containing the body\
-                                    text to submit.",
+                                text to submit.",
                                 )
                                 .takes_value(true),
                         )
@@ -95,92 +942,274 @@ async fn main() {
                                 .long("richtext-json-file")
                                 .help(
                                     "A file containing richtext JSON \
-                                    data to submit.",
+                                data to submit.",
                                 )
                                 .takes_value(true),
                         ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("video")
+                        .about("Submit a video.")
+                        .arg(no_inbox_replies_arg())
+                        .arg(
+                            clap::Arg::with_name("subreddit")
+                                .help(SUBREDDIT_HELP)
+                                .required(true)
+                                .use_delimiter(true),
+                        )
+                        .arg(clap::Arg::with_name("title").required(true))
+                        .arg(
+                            clap::Arg::with_name("video")
+                                .help("The video file to submit.")
+                                .required(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("thumbnail")
+                                .long("thumbnail")
+                                .help("The thumbnail image for the video.")
+                                .takes_value(true)
+                                .required(true),
+                        ),
                 ),
         )
-        .get_matches();
-
-    config_logger(matches.occurrences_of("verbosity"));
+}
 
-    let client = ClientImpl::new(client::Params {
-        user_agent: format!(
-            "{}/{} by /u/{}",
-            NAME, VERSION, AUTHOR_REDDIT_USERNAME
+/// Builds the parameters of a run from the top-level options.
+fn regreddit_params_of<'a>(
+    matches: &'a clap::ArgMatches<'_>,
+    settings: &'a Settings,
+    confirm: Option<&'a (dyn Fn(&[reddit::Object]) -> bool + Sync)>,
+    snapshot: Option<&'a app::Snapshot>,
+) -> RegredditParams<'a> {
+    RegredditParams {
+        settings,
+        listing_retries: parse_value_of(matches, "listing-retries"),
+        delete_retries: parse_value_of(matches, "delete-retries"),
+        sample: parse_optional_value_of(matches, "sample"),
+        seed: parse_optional_value_of(matches, "seed"),
+        set_over_18: matches.is_present("set-over18"),
+        ignore_whitelist: matches.is_present("ignore-whitelist"),
+        strict_subreddit_match: matches.is_present("strict-subreddit-match"),
+        only_inaccessible: matches.is_present("only-inaccessible"),
+        comment_depth: if matches.is_present("only-top-level") {
+            Some(app::CommentDepth::TopLevel)
+        } else if matches.is_present("only-replies") {
+            Some(app::CommentDepth::Replies)
+        } else {
+            None
+        },
+        randomize_order: matches.is_present("randomize-order"),
+        keep_if_trending: matches.is_present("keep-if-trending"),
+        score_min: parse_optional_value_of(matches, "score-min"),
+        score_max: parse_optional_value_of(matches, "score-max"),
+        max_listing_pages: parse_optional_value_of(
+            matches,
+            "max-listing-pages",
         ),
-    });
-    let app = AppImpl::new(Params {
-        client: Box::new(client),
-    });
-    let settings = match Settings::new() {
-        Ok(settings) => settings,
+        listing_params: listing_params_of(matches),
+        thorough: matches.is_present("thorough"),
+        concurrency: parse_value_of(matches, "concurrency"),
+        max_auth_failures: parse_value_of::<NonZeroU32>(
+            matches,
+            "max-auth-failures",
+        )
+        .get(),
+        created_after: None,
+        mod_action: parse_optional_value_of(matches, "mod-action"),
+        backup_prefs: matches.value_of("backup-prefs").map(Path::new),
+        unsave: matches.is_present("unsave"),
+        unsave_others_only: matches.is_present("unsave-others-only"),
+        confirm,
+        snapshot,
+    }
+}
+
+/// Prints the differences between two snapshot files and exits.
+fn diff_snapshots(old: &str, new: &str, format: output::Format) -> ! {
+    let read = |path: &str| match read_snapshot(path) {
+        Ok(snapshot) => snapshot,
         Err(err) => {
-            eprintln!("Failed to read settings: {}.", err);
+            eprintln!("Failed to read snapshot {}: {}.", path, err);
             process::exit(1);
         }
     };
+    let diff = diff::diff(&read(old), &read(new));
 
-    if let Some(matches) = matches.subcommand_matches("submit") {
-        if let Some(matches) = matches.subcommand_matches("link") {
-            match app
-                .submit_link(&app::SubmitLinkParams {
-                    credentials: &settings.credentials,
-                    subreddit: matches.value_of("subreddit").unwrap(),
-                    title: matches.value_of("title").unwrap(),
-                    url: matches.value_of("url").unwrap(),
-                })
-                .await
+    if let Err(err) = output::write_diff(format, &diff, &mut io::stdout()) {
+        eprintln!("Failed to write the diff: {}.", err);
+        process::exit(1);
+    }
+
+    process::exit(0)
+}
+
+fn read_prefs(path: &str) -> io::Result<reddit::Prefs> {
+    let file = File::open(path)?;
+
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+fn write_snapshot(path: &str, snapshot: &app::Snapshot) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    serde_json::to_writer_pretty(file, snapshot)?;
+
+    Ok(())
+}
+
+fn read_snapshot(path: &str) -> io::Result<app::Snapshot> {
+    let file = File::open(path)?;
+
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+/// Asks the user a yes or no question on the terminal. Anything but "y" or
+/// "yes" is a no.
+fn confirm(question: &str) -> bool {
+    confirm_from(question, &mut io::stdin().lock())
+}
+
+/// Asks `question` and reads the answer from `input`.
+fn confirm_from<R: io::BufRead>(question: &str, input: &mut R) -> bool {
+    eprint!("{} [y/N] ", question);
+
+    let mut answer = String::new();
+
+    if input.read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn no_inbox_replies_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("no-inbox-replies")
+        .long("no-inbox-replies")
+        .help("Don't send replies to the post to your inbox.")
+}
+
+/// The subreddits to submit to. They are given as one comma-separated
+/// positional, since only the last positional may take several values and the
+/// title comes after it.
+fn subreddits_of<'a>(matches: &'a clap::ArgMatches<'_>) -> Vec<&'a str> {
+    matches
+        .values_of("subreddit")
+        .unwrap()
+        .map(str::trim)
+        .filter(|subreddit| !subreddit.is_empty())
+        .collect()
+}
+
+fn listing_params_of(
+    matches: &clap::ArgMatches<'_>,
+) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+
+    for param in matches.values_of("listing-param").into_iter().flatten() {
+        let mut parts = param.splitn(2, '=');
+
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value))
+                if !key.is_empty()
+                    && !reddit::ListingControl::FIELDS.contains(&key) =>
             {
-                Ok(_res) => process::exit(0),
-                Err(err) => {
-                    eprintln!("{}", err);
-                    process::exit(1)
-                }
+                params.insert(key.to_string(), value.to_string());
             }
-        }
-
-        if let Some(matches) = matches.subcommand_matches("self-post") {
-            match app
-                .submit_self_post(&app::SubmitSelfPostParams {
-                    credentials: &settings.credentials,
-                    subreddit: matches.value_of("subreddit").unwrap(),
-                    title: matches.value_of("title").unwrap(),
-                    text: matches.value_of("text"),
-                    text_file: matches.value_of("text-file"),
-                    richtext_json: matches.value_of("richtext-json"),
-                    richtext_json_file: matches.value_of("richtext-json-file"),
-                })
-                .await
+            (Some(key), Some(_))
+                if reddit::ListingControl::FIELDS.contains(&key) =>
             {
-                Ok(_res) => process::exit(0),
-                Err(err) => {
-                    eprintln!("{}", err);
-                    process::exit(1)
-                }
+                eprintln!("`--listing-param` can't set `{}`.", key);
+                process::exit(1);
+            }
+            _ => {
+                eprintln!(
+                    "Invalid `--listing-param` {:?}. Expected `key=value`.",
+                    param
+                );
+                process::exit(1);
             }
         }
     }
 
-    if !matches.is_present("yes") {
-        eprintln!("You did not specify the `--yes` flag. Exiting...");
-        process::exit(1);
+    params
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("Warning: {}.", warning);
     }
+}
 
-    match app
-        .regreddit(&RegredditParams {
-            settings: &settings,
-        })
-        .await
-    {
-        Ok(_) => eprintln!("Successfully nuked your Reddit account."),
+/// Prints the outcome of each submission and returns the exit code.
+fn report_submissions(submissions: &[app::Submission]) -> i32 {
+    let mut code = 0;
+
+    for submission in submissions {
+        match (&submission.error, &submission.name) {
+            (None, Some(name)) => {
+                eprintln!("Submitted {} to r/{}.", name, submission.subreddit)
+            }
+            (None, None) => {
+                eprintln!("Submitted to r/{}.", submission.subreddit)
+            }
+            (Some(err), _) => {
+                eprintln!(
+                    "Failed to submit to r/{}: {}.",
+                    submission.subreddit, err
+                );
+                code = 1;
+            }
+        }
+    }
+
+    code
+}
+
+fn verify_config() -> ! {
+    let settings = match Settings::new() {
+        Ok(settings) => settings,
         Err(err) => {
-            eprintln!("Error {:?}", err);
+            eprintln!("Failed to read settings: {}.", err);
+            process::exit(1);
         }
+    };
+    let problems = settings.validate();
+
+    if problems.is_empty() {
+        println!("Config OK.");
+        process::exit(0);
+    }
+
+    eprintln!("Found {} problem(s) in the config:", problems.len());
+
+    for problem in problems {
+        eprintln!("  - {}", problem);
     }
+
+    process::exit(1);
+}
+
+fn parse_value_of<T: FromStr>(matches: &clap::ArgMatches<'_>, name: &str) -> T {
+    parse_optional_value_of(matches, name).unwrap()
 }
 
+fn parse_optional_value_of<T: FromStr>(
+    matches: &clap::ArgMatches<'_>,
+    name: &str,
+) -> Option<T> {
+    let value = matches.value_of(name)?;
+
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("Invalid value for `--{}`: {}.", name, value);
+            process::exit(1);
+        }
+    }
+}
+
+/// Installs a logger writing to stderr. Only the binary sets up logging; the
+/// rest of the crate just uses the `log` macros.
 fn config_logger(verbosity: u64) {
     let stderr = log4rs::append::console::ConsoleAppender::builder()
         .encoder(Box::new(log4rs::encode::pattern::PatternEncoder::new(
@@ -210,5 +1239,245 @@ fn config_logger(verbosity: u64) {
                 .build(level_filter),
         )
         .unwrap();
-    let _ = log4rs::init_config(config).unwrap();
+
+    // Leave an already installed logger alone instead of panicking, so this
+    // can be called more than once.
+    if log4rs::init_config(config).is_err() {
+        log::debug!("A logger is already installed.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::{self, MockClient, MockState};
+
+    #[test]
+    fn several_subreddits_are_submitted_to() {
+        let matches = cli().get_matches_from(vec![
+            "regreddit",
+            "submit",
+            "link",
+            "rust, AskReddit,,pics",
+            "A title",
+            "https://example.com",
+        ]);
+        let (_, submit_matches) = matches.subcommand();
+        let (_, link_matches) = submit_matches.unwrap().subcommand();
+
+        assert_eq!(
+            subreddits_of(link_matches.unwrap()),
+            vec!["rust", "AskReddit", "pics"]
+        );
+    }
+
+    #[tokio::test]
+    async fn preview_is_confirmed_before_deleting() {
+        let settings = testing::settings(&[]);
+
+        for &(answer, deleted) in &[("yes\n", 2), ("\n", 0)] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![
+                    testing::comment("a", "rust"),
+                    testing::comment("b", "rust"),
+                ]],
+                ..MockState::default()
+            });
+            let previewed = Mutex::new(None);
+            let preview = |items: &[reddit::Object]| {
+                let mut plan = Vec::new();
+
+                output::write_plan(items, &mut plan).unwrap();
+                *previewed.lock().unwrap() =
+                    Some((String::from_utf8(plan).unwrap(), client.deleted()));
+
+                confirm_from("Delete these items?", &mut answer.as_bytes())
+            };
+            let params = RegredditParams {
+                confirm: Some(&preview),
+                ..testing::params(&settings)
+            };
+
+            let report =
+                testing::app(&client).regreddit(&params).await.unwrap();
+            let (plan, deleted_before) =
+                previewed.into_inner().unwrap().unwrap();
+
+            assert!(plan.starts_with("2 comments and 0 posts"));
+            assert!(deleted_before.is_empty());
+            assert_eq!(client.deleted().len(), deleted);
+            assert_eq!(report.cancelled, deleted == 0);
+        }
+    }
+
+    #[test]
+    fn plan_fails_only_above_max_allowed() {
+        assert_eq!(plan_exit_code(99, Some(100), 2), 0);
+        assert_eq!(plan_exit_code(100, Some(100), 2), 0);
+        assert_eq!(plan_exit_code(101, Some(100), 2), 2);
+        assert_eq!(plan_exit_code(101, Some(100), 7), 7);
+        assert_eq!(plan_exit_code(1000, None, 2), 0);
+    }
+
+    #[test]
+    fn logger_can_be_configured_twice() {
+        config_logger(0);
+        config_logger(3);
+    }
+
+    #[test]
+    fn listing_params_are_sent_with_the_modeled_fields() {
+        let matches = cli().get_matches_from(vec![
+            "regreddit",
+            "--listing-param",
+            "sort=top",
+            "--listing-param",
+            "t=all",
+        ]);
+        let listing_control = reddit::ListingControl {
+            after: Some("t1_abc".to_string()),
+            before: None,
+            limit: Some(50),
+            count: None,
+            show: None,
+            sr_detail: Some(true),
+            extra: listing_params_of(&matches),
+        };
+
+        let request = reqwest::Client::new()
+            .get("https://oauth.reddit.com/user/someone/comments")
+            .query(&listing_control)
+            .build()
+            .unwrap();
+        let mut query: Vec<_> = request.url().query_pairs().collect();
+
+        query.sort();
+        assert_eq!(
+            query,
+            vec![
+                ("after".into(), "t1_abc".into()),
+                ("limit".into(), "50".into()),
+                ("sort".into(), "top".into()),
+                ("sr_detail".into(), "true".into()),
+                ("t".into(), "all".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn require_whitelist_needs_a_whitelisted_subreddit() {
+        let mut settings = testing::settings(&[]);
+
+        assert!(require_whitelist(&settings).is_err());
+
+        settings.whitelist_posts = vec!["rust".to_string()];
+
+        assert!(require_whitelist(&settings).is_ok());
+        assert!(require_whitelist(&testing::settings(&["rust"])).is_ok());
+    }
+
+    #[tokio::test]
+    async fn require_whitelist_aborts_the_run_before_deleting() {
+        let matches = cli().get_matches_from(vec![
+            "regreddit",
+            "--require-whitelist",
+            "--yes",
+        ]);
+
+        for &(whitelist, code) in &[(&[][..], 1), (&["pics"][..], 0)] {
+            let client = MockClient::new(MockState {
+                comments: vec![vec![testing::comment("a", "rust")]],
+                ..MockState::default()
+            });
+
+            assert_eq!(
+                nuke(
+                    &testing::app(&client),
+                    &testing::settings(whitelist),
+                    &matches
+                )
+                .await,
+                code
+            );
+
+            if code == 0 {
+                assert_eq!(client.deleted(), vec!["t1_a"]);
+            } else {
+                assert!(client.deleted().is_empty());
+                assert!(client.calls("basic_auth").is_empty());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_run_is_notified() {
+        let out = testing::temp_path("failed.json");
+        let command = format!("cat > '{}'", out.display());
+        let matches = cli().get_matches_from(vec![
+            "regreddit",
+            "--yes",
+            "--notify-command",
+            &command,
+        ]);
+        let client = MockClient::new(MockState {
+            successful_auths: Some(0),
+            ..MockState::default()
+        });
+
+        let code =
+            nuke(&testing::app(&client), &testing::settings(&[]), &matches)
+                .await;
+
+        let notified: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+
+        std::fs::remove_file(&out).unwrap();
+
+        assert_eq!(code, 1);
+        assert_eq!(notified["status"], "failed");
+        assert_eq!(notified["version"], VERSION);
+        assert!(notified["error"].is_string());
+        assert!(notified.get("deletions").is_none());
+    }
+
+    #[tokio::test]
+    async fn only_deleting_runs_are_recorded_for_the_min_interval() {
+        let lock = testing::temp_path("nuke.lock");
+        let last_run = format!("{}.last-run", lock.display());
+        let run = |args: &[&str]| {
+            let mut argv = vec!["regreddit", "--lock", lock.to_str().unwrap()];
+
+            argv.extend_from_slice(args);
+            cli().get_matches_from(argv)
+        };
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let app = testing::app(&client);
+
+        nuke(&app, &settings, &run(&["--dump-plan-then-exit"])).await;
+
+        assert!(!Path::new(&last_run).exists());
+
+        let failing = MockClient::new(MockState {
+            successful_auths: Some(0),
+            ..MockState::default()
+        });
+
+        assert_eq!(
+            nuke(&testing::app(&failing), &settings, &run(&["--yes"])).await,
+            1
+        );
+        assert!(!Path::new(&last_run).exists());
+
+        assert_eq!(nuke(&app, &settings, &run(&["--yes"])).await, 0);
+        assert!(Path::new(&last_run).exists());
+        assert!(!lock.exists());
+
+        std::fs::remove_file(&last_run).unwrap();
+    }
 }
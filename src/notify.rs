@@ -0,0 +1,141 @@
+//! Tells the user a run finished, for runs nobody is watching.
+
+use std::io;
+use std::process::Stdio;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::output::{JsonSummary, RunInfo};
+
+/// What notifications get about a run, with a `status` saying how it ended.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum Notification<'a> {
+    /// The same summary `--output json` prints.
+    Succeeded(JsonSummary<'a>),
+    /// The run stopped with `error` before it could be summarized.
+    Failed {
+        #[serde(flatten)]
+        run: &'a RunInfo<'a>,
+        error: String,
+    },
+}
+
+/// Runs `command` with the shell, writing `summary` as JSON to its stdin.
+pub(crate) async fn run_command<T: serde::Serialize>(
+    command: &str,
+    summary: &T,
+) -> Result<()> {
+    let body = serde_json::to_vec(summary)
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't need the summary may exit without reading
+        // it, which is fine.
+        match stdin.write_all(&body).await {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+            res => res?,
+        }
+    }
+
+    let status = child.await?;
+
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Io,
+            format!("`{}` exited with {}", command, status),
+        ));
+    }
+
+    Ok(())
+}
+
+/// POSTs `summary` as JSON to `url`.
+pub(crate) async fn post_webhook<T: serde::Serialize>(
+    url: &url::Url,
+    summary: &T,
+) -> Result<()> {
+    reqwest::Client::new()
+        .post(url.clone())
+        .json(summary)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::output::Filters;
+    use crate::testing::{self, MockClient, MockState, Response, Server};
+
+    #[tokio::test]
+    async fn notifications_get_the_json_summary() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&["pics"]);
+        let params = testing::params(&settings);
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+        let run = RunInfo {
+            version: "v1.2.3",
+            filters: Filters::of(&params),
+        };
+        let summary = Notification::Succeeded(JsonSummary {
+            run: &run,
+            report: &report,
+        });
+        let server = Server::new();
+        let out = testing::temp_path("notify.json");
+
+        server.serve(vec![Response::ok("")]);
+        post_webhook(&server.url().parse().unwrap(), &summary)
+            .await
+            .unwrap();
+        run_command(&format!("cat > '{}'", out.display()), &summary)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        let posted: serde_json::Value =
+            serde_json::from_slice(&request.body).unwrap();
+        let piped: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+
+        std::fs::remove_file(out).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(posted["status"], "succeeded");
+        assert_eq!(posted["version"], "v1.2.3");
+        assert_eq!(posted["filters"]["comment_whitelist"][0], "pics");
+        assert_eq!(posted["comments"]["deleted"], 1);
+        assert_eq!(posted["deletions"][0]["name"], "t1_a");
+        assert_eq!(piped, posted);
+    }
+
+    #[tokio::test]
+    async fn command_that_does_not_read_the_summary_is_fine() {
+        // Bigger than a pipe buffer, so writing it fails once `true` exits.
+        let summary = "x".repeat(1 << 20);
+
+        run_command("true", &summary).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_command_is_an_error() {
+        let err = run_command("exit 3", &"summary").await.unwrap_err();
+
+        assert!(err.to_string().contains("exit"));
+    }
+}
@@ -0,0 +1,522 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::app::{AgeRange, Counts, RegredditParams, RunReport};
+use crate::diff::SnapshotDiff;
+use crate::reddit;
+
+const TITLE_MAX_CHARS: usize = 60;
+const CSV_HEADER: [&str; 5] = ["kind", "name", "subreddit", "deleted", "error"];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown output format {}", s)),
+        }
+    }
+}
+
+/// What a run was and how it was asked to run, so its JSON summary describes
+/// itself.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunInfo<'a> {
+    pub version: &'a str,
+    pub filters: Filters<'a>,
+}
+
+/// The options of a run that decide what gets deleted.
+#[derive(Debug, Serialize)]
+pub(crate) struct Filters<'a> {
+    pub comment_whitelist: BTreeSet<String>,
+    pub post_whitelist: BTreeSet<String>,
+    pub ignore_whitelist: bool,
+    pub strict_subreddit_match: bool,
+    pub only_inaccessible: bool,
+    pub comment_depth: Option<&'static str>,
+    pub created_after: Option<f64>,
+    pub keep_if_trending: bool,
+    pub score_min: Option<i64>,
+    pub score_max: Option<i64>,
+    pub sample: Option<usize>,
+    pub randomize_order: bool,
+    pub seed: Option<u64>,
+    pub max_listing_pages: Option<u32>,
+    pub thorough: bool,
+    pub listing_params: BTreeMap<&'a str, &'a str>,
+    pub mod_action: Option<&'static str>,
+    pub unsave: bool,
+    pub unsave_others_only: bool,
+    pub from_snapshot: bool,
+}
+
+impl<'a> Filters<'a> {
+    pub(crate) fn of(p: &'a RegredditParams<'_>) -> Self {
+        Filters {
+            comment_whitelist: p
+                .settings
+                .comment_whitelist()
+                .into_iter()
+                .collect(),
+            post_whitelist: p.settings.post_whitelist().into_iter().collect(),
+            ignore_whitelist: p.ignore_whitelist,
+            strict_subreddit_match: p.strict_subreddit_match,
+            only_inaccessible: p.only_inaccessible,
+            comment_depth: p.comment_depth.map(|depth| depth.as_str()),
+            created_after: p.created_after,
+            keep_if_trending: p.keep_if_trending,
+            score_min: p.score_min,
+            score_max: p.score_max,
+            sample: p.sample,
+            randomize_order: p.randomize_order,
+            seed: p.seed,
+            max_listing_pages: p.max_listing_pages,
+            thorough: p.thorough,
+            listing_params: p
+                .listing_params
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect(),
+            mod_action: p.mod_action.map(|action| action.as_str()),
+            unsave: p.unsave,
+            unsave_others_only: p.unsave_others_only,
+            from_snapshot: p.snapshot.is_some(),
+        }
+    }
+}
+
+/// The JSON summary of a run, as printed by `--output json` and sent to
+/// notifications.
+#[derive(Serialize)]
+pub(crate) struct JsonSummary<'a> {
+    #[serde(flatten)]
+    pub run: &'a RunInfo<'a>,
+    #[serde(flatten)]
+    pub report: &'a RunReport,
+}
+
+/// Writes the report of a run in the given format.
+pub(crate) fn write_report<W: Write>(
+    format: Format,
+    run: &RunInfo<'_>,
+    report: &RunReport,
+    w: &mut W,
+) -> io::Result<()> {
+    match format {
+        Format::Text => write_text(report, w),
+        Format::Json => {
+            serde_json::to_writer_pretty(
+                &mut *w,
+                &JsonSummary { run, report },
+            )?;
+            writeln!(w)
+        }
+        Format::Csv => write_csv(report, w),
+    }
+}
+
+/// Writes the differences between two snapshots in the given format.
+pub(crate) fn write_diff<W: Write>(
+    format: Format,
+    diff: &SnapshotDiff,
+    w: &mut W,
+) -> io::Result<()> {
+    match format {
+        Format::Text => {
+            for name in &diff.added {
+                writeln!(w, "+ {}", name)?;
+            }
+
+            for name in &diff.removed {
+                writeln!(w, "- {}", name)?;
+            }
+
+            for change in &diff.changed {
+                let mut what = Vec::new();
+
+                if let Some((old, new)) = change.score {
+                    what.push(format!("score {} -> {}", old, new));
+                }
+
+                if change.text_changed {
+                    what.push("text edited".to_string());
+                }
+
+                writeln!(w, "~ {} ({})", change.name, what.join(", "))?;
+            }
+
+            writeln!(
+                w,
+                "{} added, {} removed, {} changed.",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len()
+            )
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, diff)?;
+            writeln!(w)
+        }
+        Format::Csv => {
+            write_csv_record(w, &["change", "name"])?;
+
+            for name in &diff.added {
+                write_csv_record(w, &["added", name])?;
+            }
+
+            for name in &diff.removed {
+                write_csv_record(w, &["removed", name])?;
+            }
+
+            for change in &diff.changed {
+                write_csv_record(w, &["changed", &change.name])?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Writes a summary of the items a run is about to delete.
+pub(crate) fn write_plan<W: Write>(
+    items: &[reddit::Object],
+    w: &mut W,
+) -> io::Result<()> {
+    let comments = items
+        .iter()
+        .filter(|item| matches!(item, reddit::Object::Comment { .. }))
+        .count();
+    let posts = items
+        .iter()
+        .filter(|item| matches!(item, reddit::Object::Link { .. }))
+        .count();
+    let mut subreddits = BTreeMap::new();
+
+    for subreddit in items.iter().filter_map(|item| item.subreddit()) {
+        *subreddits.entry(subreddit).or_insert(0) += 1;
+    }
+
+    writeln!(
+        w,
+        "{} comments and {} posts will be deleted.",
+        comments, posts
+    )?;
+
+    for (subreddit, count) in subreddits {
+        writeln!(w, "  r/{}: {}", subreddit, count)?;
+    }
+
+    let ages = AgeRange::of(items);
+
+    if let Some(oldest) = ages.oldest {
+        writeln!(w, "Oldest item: {}", format_timestamp(oldest))?;
+    }
+
+    if let Some(newest) = ages.newest {
+        writeln!(w, "Newest item: {}", format_timestamp(newest))?;
+    }
+
+    Ok(())
+}
+
+/// What to list for each item in a plan.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct PlanItems {
+    /// Describe each item, with the title of the post comments are on.
+    pub context: bool,
+    /// Print each item's permalink.
+    pub permalinks: bool,
+}
+
+/// Writes one line per item about to be deleted.
+pub(crate) fn write_plan_items<W: Write>(
+    items: &[reddit::Object],
+    what: PlanItems,
+    w: &mut W,
+) -> io::Result<()> {
+    if !what.context && !what.permalinks {
+        return Ok(());
+    }
+
+    for item in items {
+        let mut line = Vec::new();
+
+        if what.context {
+            line.push(describe(item));
+        }
+
+        if what.permalinks {
+            line.extend(item.permalink());
+        }
+
+        writeln!(w, "  {}", line.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// Describes a comment or post for a human, e.g.
+/// `comment on "Some title" in r/rust`.
+pub(crate) fn describe(item: &reddit::Object) -> String {
+    match item {
+        reddit::Object::Comment {
+            link_title,
+            subreddit,
+            ..
+        } => format!(
+            "comment on \"{}\" in r/{}",
+            truncate(link_title, TITLE_MAX_CHARS),
+            subreddit
+        ),
+        reddit::Object::Link {
+            title, subreddit, ..
+        } => format!(
+            "post \"{}\" in r/{}",
+            truncate(title, TITLE_MAX_CHARS),
+            subreddit
+        ),
+        _ => item.kind_name().to_string(),
+    }
+}
+
+/// Shortens `s` to at most `max_chars` characters, marking the cut with an
+/// ellipsis. Never splits a character.
+pub(crate) fn truncate(s: &str, max_chars: usize) -> Cow<'_, str> {
+    match s.char_indices().nth(max_chars.saturating_sub(1)) {
+        Some((end, _)) if s[end..].chars().count() > 1 => {
+            Cow::Owned(format!("{}…", &s[..end]))
+        }
+        _ => Cow::Borrowed(s),
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC date and time.
+pub(crate) fn format_timestamp(secs: f64) -> String {
+    match chrono::NaiveDateTime::from_timestamp_opt(secs as i64, 0) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => secs.to_string(),
+    }
+}
+
+fn write_age_range<W: Write>(
+    w: &mut W,
+    kind: &str,
+    ages: AgeRange,
+) -> io::Result<()> {
+    if let (Some(oldest), Some(newest)) = (ages.oldest, ages.newest) {
+        writeln!(
+            w,
+            "Oldest {} seen: {}. Newest {} seen: {}.",
+            kind,
+            format_timestamp(oldest),
+            kind,
+            format_timestamp(newest)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_text<W: Write>(report: &RunReport, w: &mut W) -> io::Result<()> {
+    writeln!(
+        w,
+        "Run from {} to {}.",
+        format_timestamp(report.started_at),
+        format_timestamp(report.finished_at)
+    )?;
+    write_counts(w, "Comments", report.comments)?;
+    write_counts(w, "Posts", report.posts)?;
+
+    if report.unsaved > 0 {
+        writeln!(w, "Unsaved {} items.", report.unsaved)?;
+    }
+
+    for (reason, count) in &report.skipped {
+        writeln!(w, "Skipped {} items: {}.", count, reason.as_str())?;
+    }
+
+    write_age_range(w, "comment", report.comment_ages)?;
+    write_age_range(w, "post", report.post_ages)?;
+
+    for (subreddit, counts) in &report.subreddits {
+        write_counts(w, &format!("r/{}", subreddit), *counts)?;
+    }
+
+    for deletion in report.failures() {
+        writeln!(
+            w,
+            "Failed to delete {}: {}.",
+            deletion.name,
+            deletion.error.as_deref().unwrap_or_default()
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_counts<W: Write>(
+    w: &mut W,
+    label: &str,
+    counts: Counts,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "{}: {} deleted, {} failed, {} skipped.",
+        label, counts.deleted, counts.failed, counts.skipped
+    )
+}
+
+fn write_csv<W: Write>(report: &RunReport, w: &mut W) -> io::Result<()> {
+    write_csv_record(w, &CSV_HEADER)?;
+
+    for deletion in &report.deletions {
+        write_csv_record(
+            w,
+            &[
+                deletion.kind,
+                deletion.name.as_str(),
+                deletion.subreddit.as_str(),
+                if deletion.deleted { "true" } else { "false" },
+                deletion.error.as_deref().unwrap_or_default(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes one CSV record, quoting fields as described in RFC 4180.
+pub(crate) fn write_csv_record<W: Write>(
+    w: &mut W,
+    fields: &[&str],
+) -> io::Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            write!(w, ",")?;
+        }
+
+        if field.contains(&[',', '"', '\n', '\r'][..]) {
+            write!(w, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            write!(w, "{}", field)?;
+        }
+    }
+
+    write!(w, "\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::testing::{self, MockClient, MockState};
+
+    #[tokio::test]
+    async fn csv_has_one_row_per_deletion() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![
+                testing::comment("a", "rust"),
+                testing::comment("b", "AskReddit"),
+            ]],
+            posts: vec![vec![testing::post("c", "rust")]],
+            failures: vec![("delete_link", 1)].into_iter().collect(),
+            ..MockState::default()
+        });
+        let settings = testing::settings(&[]);
+        let report = testing::app(&client)
+            .regreddit(&testing::params(&settings))
+            .await
+            .unwrap();
+        let mut csv = Vec::new();
+
+        write_csv(&report, &mut csv).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<Vec<&str>> =
+            csv.lines().map(|row| row.split(',').collect()).collect();
+
+        assert_eq!(rows[0], CSV_HEADER);
+        assert_eq!(rows.len(), 1 + 3);
+
+        for (row, deletion) in rows[1..].iter().zip(&report.deletions) {
+            assert_eq!(
+                row,
+                &[
+                    deletion.kind,
+                    deletion.name.as_str(),
+                    deletion.subreddit.as_str(),
+                    if deletion.deleted { "true" } else { "false" },
+                    deletion.error.as_deref().unwrap_or_default(),
+                ]
+            );
+        }
+
+        assert_eq!(report.failures().count(), 1);
+    }
+
+    #[test]
+    fn comment_is_described_by_its_post_title() {
+        let mut comment = testing::comment("a", "rust");
+
+        assert_eq!(
+            describe(&comment),
+            "comment on \"Post in r/rust\" in r/rust"
+        );
+
+        if let reddit::Object::Comment {
+            ref mut link_title, ..
+        } = comment
+        {
+            *link_title = "é".repeat(100);
+        }
+
+        assert_eq!(
+            describe(&comment),
+            format!("comment on \"{}…\" in r/rust", "é".repeat(59))
+        );
+    }
+
+    #[tokio::test]
+    async fn json_summary_describes_the_run() {
+        let client = MockClient::new(MockState {
+            comments: vec![vec![testing::comment("a", "rust")]],
+            ..MockState::default()
+        });
+        let settings = testing::settings(&["pics"]);
+        let params = crate::app::RegredditParams {
+            score_max: Some(100),
+            ..testing::params(&settings)
+        };
+        let report = testing::app(&client).regreddit(&params).await.unwrap();
+        let run = RunInfo {
+            version: "v1.2.3",
+            filters: Filters::of(&params),
+        };
+        let mut json = Vec::new();
+
+        write_report(Format::Json, &run, &report, &mut json).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+
+        assert_eq!(json["version"], "v1.2.3");
+        assert_eq!(json["filters"]["comment_whitelist"][0], "pics");
+        assert_eq!(json["filters"]["score_max"], 100);
+        assert_eq!(json["filters"]["from_snapshot"], false);
+        assert!(json["started_at"].is_number());
+        assert!(json["finished_at"].is_number());
+        assert_eq!(json["comments"]["deleted"], 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket that paces requests to a number per minute.
+pub(crate) struct RateLimiter {
+    interval: Duration,
+    burst: u32,
+    state: Mutex<State>,
+}
+
+struct State {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `rate` requests per minute, at most `burst`
+    /// of them back to back.
+    pub(crate) fn per_minute(rate: u32, burst: u32) -> Self {
+        assert!(rate > 0, "rate must be positive");
+        assert!(burst > 0, "burst must be positive");
+
+        RateLimiter {
+            interval: Duration::from_secs(60) / rate,
+            burst,
+            state: Mutex::new(State {
+                tokens: f64::from(burst),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let refill = (now - state.refilled_at).as_secs_f64()
+                    / self.interval.as_secs_f64();
+
+                state.tokens =
+                    (state.tokens + refill).min(f64::from(self.burst));
+                state.refilled_at = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+
+                self.interval.mul_f64(1.0 - state.tokens)
+            };
+
+            tokio::time::delay_for(wait).await;
+        }
+    }
+}
+
+/// Tracks the allowance Reddit reports in its `X-Ratelimit-*` headers and
+/// holds requests back once it is used up, instead of letting them fail with
+/// 429s.
+pub(crate) struct Allowance {
+    resets_at: Mutex<Option<Instant>>,
+}
+
+impl Allowance {
+    pub(crate) fn new() -> Self {
+        Allowance {
+            resets_at: Mutex::new(None),
+        }
+    }
+
+    /// Records the allowance reported by a response.
+    pub(crate) fn update(&self, headers: &reqwest::header::HeaderMap) {
+        let remaining = header_value(headers, "x-ratelimit-remaining");
+        let reset = header_value(headers, "x-ratelimit-reset");
+        let (remaining, reset) = match (remaining, reset) {
+            (Some(remaining), Some(reset)) => (remaining, reset.max(0.0)),
+            _ => return,
+        };
+
+        if remaining >= 1.0 {
+            return;
+        }
+
+        let resets_at = Instant::now() + Duration::from_secs_f64(reset);
+        let mut current = self.resets_at.lock().unwrap();
+
+        if current.map_or(true, |current| current < Instant::now()) {
+            let local = chrono::Local::now()
+                + chrono::Duration::milliseconds((reset * 1000.0) as i64);
+
+            // Printed rather than logged, since the default log level hides
+            // warnings and the run would seem stuck.
+            eprintln!(
+                "Rate limit reached, pausing until {}.",
+                local.format("%H:%M")
+            );
+        }
+
+        *current = Some(resets_at);
+    }
+
+    /// Waits until the allowance has been reset, if it is used up.
+    pub(crate) async fn wait(&self) {
+        let resets_at = *self.resets_at.lock().unwrap();
+
+        if let Some(resets_at) = resets_at {
+            let now = Instant::now();
+
+            if resets_at > now {
+                tokio::time::delay_for(resets_at - now).await;
+            }
+        }
+    }
+}
+
+fn header_value(
+    headers: &reqwest::header::HeaderMap,
+    name: &str,
+) -> Option<f64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn acquires_are_paced_to_the_rate() {
+        // One every 10ms, after the first.
+        let limiter = RateLimiter::per_minute(6000, 1);
+        let started = Instant::now();
+
+        for _ in 0..11 {
+            limiter.acquire().await;
+        }
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn burst_is_not_paced() {
+        let limiter = RateLimiter::per_minute(1, 5);
+        let started = Instant::now();
+
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    fn headers(remaining: &str, reset: &str) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+
+        headers.insert("x-ratelimit-remaining", remaining.parse().unwrap());
+        headers.insert("x-ratelimit-reset", reset.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn used_up_allowance_waits_for_the_reset() {
+        let allowance = Allowance::new();
+        let started = Instant::now();
+
+        allowance.update(&headers("0.0", "0.2"));
+        allowance.wait().await;
+
+        let waited = started.elapsed();
+
+        assert!(waited >= Duration::from_millis(200));
+        assert!(waited < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn remaining_allowance_does_not_wait() {
+        let allowance = Allowance::new();
+        let started = Instant::now();
+
+        allowance.update(&headers("10", "300"));
+        allowance.wait().await;
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1,8 +1,27 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 #[derive(Serialize)]
 pub(crate) struct DeleteRequestForm<'a> {
     pub id: &'a str,
 }
 
+#[derive(Serialize)]
+pub(crate) struct ApproveRequestForm<'a> {
+    pub id: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct UnsaveRequestForm<'a> {
+    pub id: &'a str,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RemoveRequestForm<'a> {
+    pub id: &'a str,
+    pub spam: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct ListingControl {
     pub after: Option<String>,
@@ -10,6 +29,18 @@ pub(crate) struct ListingControl {
     pub limit: Option<u32>,
     pub count: Option<u32>,
     pub show: Option<ListingShow>,
+    /// Expand each item with details about its subreddit.
+    pub sr_detail: Option<bool>,
+    /// Query parameters not modeled above.
+    #[serde(flatten)]
+    pub extra: HashMap<String, String>,
+}
+
+impl ListingControl {
+    /// The query parameters modeled by `ListingControl` itself, which may not
+    /// be passed through `extra`.
+    pub(crate) const FIELDS: &'static [&'static str] =
+        &["after", "before", "limit", "count", "show", "sr_detail"];
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -18,7 +49,7 @@ pub(crate) enum ListingShow {
     All,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "kind", content = "data")]
 pub(crate) enum Object {
     Listing {
@@ -30,19 +61,265 @@ pub(crate) enum Object {
     },
     #[serde(rename = "t1")]
     Comment {
+        #[serde(default)]
+        author: String,
         link_title: String,
         link_id: String,
+        /// The fullname of the post or comment this is a reply to.
+        #[serde(default)]
+        parent_id: String,
         name: String,
         subreddit: String,
+        created_utc: f64,
+        #[serde(default)]
+        score: i64,
+        #[serde(default)]
+        body: String,
+        #[serde(default)]
+        sr_detail: Option<SubredditDetail>,
     },
     #[serde(rename = "t3")]
     Link {
+        #[serde(default)]
+        author: String,
         subreddit: String,
         title: String,
         name: String,
+        created_utc: f64,
+        #[serde(default)]
+        score: i64,
+        #[serde(default)]
+        is_self: bool,
+        /// The linked URL, or the post's own URL for self-posts.
+        #[serde(default)]
+        url: String,
+        /// Empty for link posts.
+        #[serde(default)]
+        selftext: String,
+        #[serde(default)]
+        sr_detail: Option<SubredditDetail>,
+    },
+    /// A private message.
+    #[serde(rename = "t4")]
+    Message {
+        name: String,
+        /// Missing for messages from Reddit itself.
+        #[serde(default)]
+        author: Option<String>,
+        /// Set for messages sent from or to a subreddit.
+        #[serde(default)]
+        subreddit: Option<String>,
+        subject: String,
+        body: String,
+        created_utc: f64,
     },
+    #[serde(rename = "t5")]
+    Subreddit {
+        name: String,
+        display_name: String,
+        /// Hidden for some private subreddits.
+        #[serde(default)]
+        subscribers: Option<u64>,
+        created_utc: f64,
+    },
+    /// A "load more" stub holding the ids of comments left out of a listing.
+    #[serde(rename = "more")]
+    More { children: Vec<String> },
 }
 
+impl Object {
+    /// The fullname of the object, e.g. `t1_abc123`.
+    pub(crate) fn name(&self) -> Option<&str> {
+        match self {
+            Object::Comment { name, .. }
+            | Object::Link { name, .. }
+            | Object::Message { name, .. }
+            | Object::Subreddit { name, .. } => Some(name),
+            Object::Listing { .. } | Object::More { .. } => None,
+        }
+    }
+
+    /// The username of the object's author.
+    pub(crate) fn author(&self) -> Option<&str> {
+        match self {
+            Object::Comment { author, .. } | Object::Link { author, .. } => {
+                Some(author)
+            }
+            Object::Message { author, .. } => author.as_deref(),
+            Object::Listing { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => None,
+        }
+    }
+
+    pub(crate) fn subreddit(&self) -> Option<&str> {
+        match self {
+            Object::Comment { subreddit, .. }
+            | Object::Link { subreddit, .. } => Some(subreddit),
+            Object::Message { subreddit, .. } => subreddit.as_deref(),
+            Object::Subreddit { display_name, .. } => Some(display_name),
+            Object::Listing { .. } | Object::More { .. } => None,
+        }
+    }
+
+    /// A link to the object on Reddit, rebuilt from the listing fields.
+    pub(crate) fn permalink(&self) -> Option<String> {
+        match self {
+            Object::Comment {
+                link_id,
+                name,
+                subreddit,
+                ..
+            } => Some(format!(
+                "https://reddit.com/r/{}/comments/{}/_/{}/",
+                subreddit,
+                strip_kind(link_id),
+                strip_kind(name)
+            )),
+            Object::Link {
+                name, subreddit, ..
+            } => Some(format!(
+                "https://reddit.com/r/{}/comments/{}/",
+                subreddit,
+                strip_kind(name)
+            )),
+            Object::Message { name, .. } => Some(format!(
+                "https://reddit.com/message/messages/{}/",
+                strip_kind(name)
+            )),
+            Object::Subreddit { display_name, .. } => {
+                Some(format!("https://reddit.com/r/{}/", display_name))
+            }
+            Object::Listing { .. } | Object::More { .. } => None,
+        }
+    }
+
+    /// When the object was created, in seconds since the Unix epoch.
+    pub(crate) fn created_utc(&self) -> Option<f64> {
+        match self {
+            Object::Comment { created_utc, .. }
+            | Object::Link { created_utc, .. }
+            | Object::Message { created_utc, .. }
+            | Object::Subreddit { created_utc, .. } => Some(*created_utc),
+            Object::Listing { .. } | Object::More { .. } => None,
+        }
+    }
+
+    /// The object's score, i.e. its upvotes minus its downvotes.
+    pub(crate) fn score(&self) -> Option<i64> {
+        match self {
+            Object::Comment { score, .. } | Object::Link { score, .. } => {
+                Some(*score)
+            }
+            Object::Listing { .. }
+            | Object::Message { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => None,
+        }
+    }
+
+    /// Whether the object is a comment replying to a post rather than to
+    /// another comment. `None` for anything else, or when the parent isn't
+    /// known.
+    pub(crate) fn is_top_level_comment(&self) -> Option<bool> {
+        match self {
+            Object::Comment { parent_id, .. } if !parent_id.is_empty() => {
+                Some(parent_id.starts_with("t3_"))
+            }
+            _ => None,
+        }
+    }
+
+    /// The text of a comment, a self-post or a message.
+    pub(crate) fn text(&self) -> Option<&str> {
+        match self {
+            Object::Comment { body, .. } | Object::Message { body, .. } => {
+                Some(body)
+            }
+            Object::Link { selftext, .. } => Some(selftext),
+            Object::Listing { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => None,
+        }
+    }
+
+    /// Whether the object came with an `sr_detail`, which the listing has to
+    /// ask for.
+    pub(crate) fn has_sr_detail(&self) -> bool {
+        match self {
+            Object::Comment { sr_detail, .. }
+            | Object::Link { sr_detail, .. } => sr_detail.is_some(),
+            Object::Listing { .. }
+            | Object::Message { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => false,
+        }
+    }
+
+    /// Whether the object's subreddit is banned, private or quarantined, going
+    /// by its `sr_detail`. Only known when the listing asked for `sr_detail`.
+    pub(crate) fn in_inaccessible_subreddit(&self) -> bool {
+        match self {
+            Object::Comment { sr_detail, .. }
+            | Object::Link { sr_detail, .. } => {
+                sr_detail.as_ref().map_or(false, |d| d.is_inaccessible())
+            }
+            Object::Listing { .. }
+            | Object::Message { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => false,
+        }
+    }
+
+    /// Whether the user moderates the object's subreddit, going by its
+    /// `sr_detail`. Only known when the listing asked for `sr_detail`.
+    pub(crate) fn user_is_moderator(&self) -> bool {
+        match self {
+            Object::Comment { sr_detail, .. }
+            | Object::Link { sr_detail, .. } => {
+                sr_detail.as_ref().map_or(false, |d| d.user_is_moderator)
+            }
+            Object::Listing { .. }
+            | Object::Message { .. }
+            | Object::Subreddit { .. }
+            | Object::More { .. } => false,
+        }
+    }
+
+    /// A human readable name for the kind of the object.
+    pub(crate) fn kind_name(&self) -> &'static str {
+        match self {
+            Object::Listing { .. } => "listing",
+            Object::Comment { .. } => "comment",
+            Object::Link { .. } => "post",
+            Object::Message { .. } => "message",
+            Object::Subreddit { .. } => "subreddit",
+            Object::More { .. } => "more",
+        }
+    }
+}
+
+/// The `sr_detail` Reddit attaches to listing items on request.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct SubredditDetail {
+    /// Missing for banned subreddits.
+    pub subreddit_type: Option<String>,
+    #[serde(default)]
+    pub quarantine: bool,
+    #[serde(default)]
+    pub user_is_moderator: bool,
+}
+
+impl SubredditDetail {
+    pub(crate) fn is_inaccessible(&self) -> bool {
+        match self.subreddit_type.as_deref() {
+            None | Some("private") | Some("employees_only") => true,
+            Some(_) => self.quarantine,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) enum Post {
     Link {
         subreddit: String,
@@ -54,8 +331,15 @@ pub(crate) enum Post {
         title: String,
         body: SelfPostBody,
     },
+    Video {
+        subreddit: String,
+        title: String,
+        video_path: PathBuf,
+        thumbnail_path: PathBuf,
+    },
 }
 
+#[derive(Clone)]
 pub(crate) enum SelfPostBody {
     Text(String),
     RichtextJson(String),
@@ -64,11 +348,48 @@ pub(crate) enum SelfPostBody {
 #[derive(Deserialize)]
 pub(crate) struct DeleteResponse {}
 
+#[derive(Deserialize)]
+pub(crate) struct ModerateResponse {}
+
+#[derive(Deserialize)]
+pub(crate) struct UnsaveResponse {}
+
 #[derive(Deserialize)]
 pub(crate) struct GetTokenResponse {
     pub access_token: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Prefs {
+    pub over_18: bool,
+    /// Every other preference, kept as is so a backup can be restored.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct MediaAssetRequest<'a> {
+    pub filepath: &'a str,
+    pub mimetype: &'a str,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MediaAssetResponse {
+    pub args: MediaUploadLease,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MediaUploadLease {
+    pub action: String,
+    pub fields: Vec<MediaUploadField>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MediaUploadField {
+    pub name: String,
+    pub value: String,
+}
+
 #[derive(Serialize)]
 pub(crate) struct SubmitRequest<'a> {
     #[serde(rename(serialize = "sr"))]
@@ -79,9 +400,210 @@ pub(crate) struct SubmitRequest<'a> {
     pub resubmit: bool,
     pub text: Option<&'a str>,
     pub richtext_json: Option<&'a str>,
+    pub video_poster_url: Option<&'a str>,
+    /// Whether replies to the post go to the author's inbox.
+    pub send_replies: bool,
+    /// Always `json`, so the response says what the new post is called.
+    pub api_type: &'static str,
 }
 
+/// The response to a submit made with `api_type=json`.
 #[derive(Deserialize)]
 pub(crate) struct SubmitResponse {
-    pub success: bool,
+    pub json: SubmitJson,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SubmitJson {
+    /// Each error as its code, message and the offending field.
+    #[serde(default)]
+    pub errors: Vec<Vec<serde_json::Value>>,
+    pub data: Option<SubmitData>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SubmitData {
+    /// Missing for videos, which are only posted once processed.
+    pub name: Option<String>,
+}
+
+/// Whether `name` is a subreddit name Reddit would accept, without the `r/`
+/// prefix.
+pub(crate) fn is_valid_subreddit_name(name: &str) -> bool {
+    (2..=21).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Strips the kind prefix off a fullname, e.g. `t3_abc123` gives `abc123`.
+fn strip_kind(fullname: &str) -> &str {
+    match fullname.find('_') {
+        Some(i) => &fullname[i + 1..],
+        None => fullname,
+    }
+}
+
+/// Whether `name` is the fullname of a comment or a link, e.g. `t1_abc123`.
+pub(crate) fn is_valid_fullname(name: &str) -> bool {
+    match name
+        .strip_prefix("t1_")
+        .or_else(|| name.strip_prefix("t3_"))
+    {
+        Some(id) => {
+            !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+/// Extracts the fullname of a post from its permalink, e.g.
+/// `https://www.reddit.com/r/rust/comments/abc123/title/` gives `t3_abc123`.
+pub(crate) fn fullname_from_permalink(permalink: &str) -> Option<String> {
+    let mut segments = permalink.split('/');
+
+    segments.find(|segment| *segment == "comments")?;
+
+    match segments.next() {
+        Some(id)
+            if !id.is_empty()
+                && id.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Some(format!("t3_{}", id))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// An inbox listing as Reddit returns it, trimmed to a few fields more
+    /// than are modeled.
+    const INBOX: &str = r#"{
+        "kind": "Listing",
+        "data": {
+            "modhash": null,
+            "dist": 2,
+            "after": null,
+            "before": null,
+            "children": [
+                {
+                    "kind": "t4",
+                    "data": {
+                        "first_message": null,
+                        "name": "t4_1a2b3c",
+                        "author": "someone_else",
+                        "subreddit": null,
+                        "subject": "Hello",
+                        "body": "How are you?",
+                        "was_comment": false,
+                        "new": true,
+                        "created_utc": 1586217600.0,
+                        "dest": "someone"
+                    }
+                },
+                {
+                    "kind": "t4",
+                    "data": {
+                        "name": "t4_4d5e6f",
+                        "author": null,
+                        "subreddit": "rust",
+                        "subject": "You have been approved",
+                        "body": "You can now post in r/rust.",
+                        "created_utc": 1586304000.0
+                    }
+                }
+            ]
+        }
+    }"#;
+
+    /// A private subreddit's about page, which hides the subscriber count.
+    const PRIVATE_SUBREDDIT: &str = r#"{
+        "kind": "t5",
+        "data": {
+            "name": "t5_2qh1i",
+            "display_name": "AskReddit",
+            "display_name_prefixed": "r/AskReddit",
+            "subreddit_type": "private",
+            "subscribers": null,
+            "over18": false,
+            "created_utc": 1201233135.0
+        }
+    }"#;
+
+    #[test]
+    fn permalinks_are_rebuilt_from_the_listing() {
+        assert_eq!(
+            testing::comment("abc", "rust").permalink().as_deref(),
+            Some("https://reddit.com/r/rust/comments/parent/_/abc/")
+        );
+        assert_eq!(
+            testing::post("xyz", "AskReddit").permalink().as_deref(),
+            Some("https://reddit.com/r/AskReddit/comments/xyz/")
+        );
+    }
+
+    #[test]
+    fn messages_are_deserialized() {
+        let inbox: Object = serde_json::from_str(INBOX).unwrap();
+        let children = match inbox {
+            Object::Listing { children, .. } => children,
+            _ => panic!("expected a listing"),
+        };
+
+        match &children[..] {
+            [Object::Message {
+                name,
+                author,
+                subreddit,
+                subject,
+                body,
+                created_utc,
+            }, from_subreddit] => {
+                assert_eq!(name, "t4_1a2b3c");
+                assert_eq!(author.as_deref(), Some("someone_else"));
+                assert_eq!(*subreddit, None);
+                assert_eq!(subject, "Hello");
+                assert_eq!(body, "How are you?");
+                assert_eq!(*created_utc, 1586217600.0);
+
+                assert_eq!(from_subreddit.kind_name(), "message");
+                assert_eq!(from_subreddit.name(), Some("t4_4d5e6f"));
+                assert_eq!(from_subreddit.author(), None);
+                assert_eq!(from_subreddit.subreddit(), Some("rust"));
+                assert_eq!(
+                    from_subreddit.text(),
+                    Some("You can now post in r/rust.")
+                );
+            }
+            _ => panic!("expected two messages, got {:?}", children),
+        }
+    }
+
+    #[test]
+    fn subreddits_are_deserialized() {
+        let subreddit: Object =
+            serde_json::from_str(PRIVATE_SUBREDDIT).unwrap();
+
+        match &subreddit {
+            Object::Subreddit {
+                name,
+                display_name,
+                subscribers,
+                created_utc,
+            } => {
+                assert_eq!(name, "t5_2qh1i");
+                assert_eq!(display_name, "AskReddit");
+                assert_eq!(*subscribers, None);
+                assert_eq!(*created_utc, 1201233135.0);
+            }
+            _ => panic!("expected a subreddit, got {:?}", subreddit),
+        }
+
+        assert_eq!(subreddit.kind_name(), "subreddit");
+        assert_eq!(subreddit.name(), Some("t5_2qh1i"));
+        assert_eq!(subreddit.subreddit(), Some("AskReddit"));
+        assert_eq!(subreddit.text(), None);
+    }
 }
@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::backoff::Backoff;
+use crate::client;
+use crate::error::{Error, ErrorKind, Result};
+use crate::settings;
+
+/// Hands out the access token for a run and re-authenticates when Reddit
+/// rejects it, e.g. because it expired.
+///
+/// After `max_failures` consecutive failed authentications, e.g. because the
+/// password was changed mid-run, the session trips and refuses to hand out
+/// tokens, so the run stops instead of retrying pointlessly. Failed
+/// authentications are backed off from, so the ones before that are spread
+/// out.
+pub(crate) struct Session {
+    client: Arc<Box<dyn client::Client>>,
+    credentials: settings::Credentials,
+    access_token: Mutex<String>,
+    failures: AtomicU32,
+    max_failures: u32,
+    backoff: Backoff,
+}
+
+impl Session {
+    pub(crate) fn new(
+        client: Arc<Box<dyn client::Client>>,
+        credentials: settings::Credentials,
+        access_token: String,
+        max_failures: u32,
+        backoff: Backoff,
+    ) -> Self {
+        Session {
+            client,
+            credentials,
+            access_token: Mutex::new(access_token),
+            failures: AtomicU32::new(0),
+            max_failures,
+            backoff,
+        }
+    }
+
+    /// The current access token.
+    pub(crate) async fn access_token(&self) -> Result<String> {
+        self.check()?;
+
+        Ok(self.access_token.lock().await.clone())
+    }
+
+    /// Gets a new access token after `rejected` was rejected. Does nothing if
+    /// another request already replaced `rejected`.
+    pub(crate) async fn reauthenticate(&self, rejected: &str) -> Result<()> {
+        let mut access_token = self.access_token.lock().await;
+
+        if *access_token != rejected {
+            return Ok(());
+        }
+
+        self.check()?;
+        log::info!("Access token was rejected. Authenticating again...");
+
+        match self
+            .client
+            .basic_auth(&client::BasicAuthParams {
+                credentials: &self.credentials,
+            })
+            .await
+        {
+            Ok(res) => {
+                self.failures.store(0, Ordering::SeqCst);
+                *access_token = res.access_token;
+
+                Ok(())
+            }
+            Err(err) => {
+                let earlier = self.failures.fetch_add(1, Ordering::SeqCst);
+
+                log::warn!("Failed to authenticate again: {}.", err);
+                self.check()?;
+
+                // The token stays locked, so other requests wait too.
+                tokio::time::delay_for(self.backoff.delay(earlier)).await;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Fails once the session has tripped.
+    pub(crate) fn check(&self) -> Result<()> {
+        let failures = self.failures.load(Ordering::SeqCst);
+
+        if failures >= self.max_failures {
+            return Err(Error::new(
+                ErrorKind::Authentication,
+                format!(
+                    "gave up after {} consecutive failed authentications; \
+                    were the credentials changed?",
+                    failures
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,29 @@
-use config::{Config, ConfigError, File};
+use std::collections::HashSet;
+
+use config::{Config, ConfigError, File, Source};
+
+use crate::reddit;
+
+const MIN_CLIENT_ID_LEN: usize = 14;
+const CREDENTIALS_KEYS: [&str; 4] =
+    ["client_id", "secret", "username", "password"];
+const TOP_LEVEL_KEYS: [&str; 3] =
+    ["whitelist", "whitelist_comments", "whitelist_posts"];
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Settings {
     pub credentials: Credentials,
     #[serde(default)]
     pub whitelist: Vec<String>,
+    /// Subreddits whose comments are kept, on top of `whitelist`.
+    #[serde(default)]
+    pub whitelist_comments: Vec<String>,
+    /// Subreddits whose posts are kept, on top of `whitelist`.
+    #[serde(default)]
+    pub whitelist_posts: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub(crate) struct Credentials {
     pub client_id: String,
     pub secret: String,
@@ -17,10 +33,266 @@ pub(crate) struct Credentials {
 
 impl Settings {
     pub(crate) fn new() -> Result<Self, ConfigError> {
+        Settings::from_file(".regreddit")
+    }
+
+    /// Reads the settings from the file `name`, with or without its
+    /// extension.
+    pub(crate) fn from_file(name: &str) -> Result<Self, ConfigError> {
         let mut s = Config::new();
 
-        s.merge(File::with_name(".regreddit").required(true))?;
+        s.merge(File::with_name(name).required(true))?;
+        normalize_keys(&mut s, "", &TOP_LEVEL_KEYS)?;
+        normalize_keys(&mut s, "credentials.", &CREDENTIALS_KEYS)?;
+
+        let missing: Vec<&str> = CREDENTIALS_KEYS
+            .iter()
+            .filter(|key| s.get_str(&format!("credentials.{}", key)).is_err())
+            .cloned()
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(ConfigError::Message(format!(
+                "missing {} under [credentials]. The accepted keys are {}",
+                missing.join(", "),
+                CREDENTIALS_KEYS.join(", ")
+            )));
+        }
 
         s.try_into()
     }
+
+    /// The subreddits whose comments are not deleted.
+    pub(crate) fn comment_whitelist(&self) -> HashSet<String> {
+        self.whitelist
+            .iter()
+            .chain(&self.whitelist_comments)
+            .cloned()
+            .collect()
+    }
+
+    /// The subreddits whose posts are not deleted.
+    pub(crate) fn post_whitelist(&self) -> HashSet<String> {
+        self.whitelist
+            .iter()
+            .chain(&self.whitelist_posts)
+            .cloned()
+            .collect()
+    }
+
+    /// Checks the settings for values Reddit would reject without making any
+    /// requests. Returns a description of every problem found.
+    pub(crate) fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let credentials = &self.credentials;
+
+        if credentials.client_id.len() < MIN_CLIENT_ID_LEN
+            || !credentials
+                .client_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            problems.push(format!(
+                "`credentials.client_id` {:?} does not look like a Reddit \
+                client ID",
+                credentials.client_id
+            ));
+        }
+
+        if credentials.secret.trim().is_empty() {
+            problems.push("`credentials.secret` is empty".to_string());
+        }
+
+        if credentials.username.trim().is_empty() {
+            problems.push("`credentials.username` is empty".to_string());
+        }
+
+        if credentials.password.is_empty() {
+            problems.push("`credentials.password` is empty".to_string());
+        }
+
+        let whitelists = [
+            ("whitelist", &self.whitelist),
+            ("whitelist_comments", &self.whitelist_comments),
+            ("whitelist_posts", &self.whitelist_posts),
+        ];
+
+        for (key, whitelist) in &whitelists {
+            for subreddit in whitelist.iter() {
+                if !reddit::is_valid_subreddit_name(subreddit) {
+                    problems.push(format!(
+                        "`{}` entry {:?} is not a valid subreddit name",
+                        key, subreddit
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// Copies the values of keys spelled differently from one of `keys`, such as
+/// `CLIENT_ID`, `clientId` or `client-id` for `client_id`, to that key.
+/// `prefix` is the table the keys are in, e.g. `credentials.`.
+fn normalize_keys(
+    s: &mut Config,
+    prefix: &str,
+    keys: &[&str],
+) -> Result<(), ConfigError> {
+    let table = if prefix.is_empty() {
+        s.collect()?
+    } else {
+        match s.get_table(prefix.trim_end_matches('.')) {
+            Ok(table) => table,
+            Err(ConfigError::NotFound(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    };
+
+    for (found, value) in table {
+        let key = keys
+            .iter()
+            .find(|key| normalize_key(key) == normalize_key(&found));
+
+        if let Some(key) = key {
+            if *key != found.as_str() {
+                s.set(&format!("{}{}", prefix, key), value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Reads settings from a TOML file with `contents`.
+    fn from_toml(name: &str, contents: &str) -> Result<Settings, ConfigError> {
+        let path = testing::temp_path(&format!("{}.toml", name));
+
+        std::fs::write(&path, contents).unwrap();
+
+        let settings = Settings::from_file(path.to_str().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        settings
+    }
+
+    #[test]
+    fn valid_settings_have_no_problems() {
+        let settings = testing::settings(&["rust", "AskReddit"]);
+
+        assert!(settings.validate().is_empty());
+    }
+
+    #[test]
+    fn short_client_id_is_a_problem() {
+        let mut settings = testing::settings(&[]);
+
+        settings.credentials.client_id = "abc".to_string();
+
+        let problems = settings.validate();
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("client_id"));
+    }
+
+    #[test]
+    fn empty_credentials_are_problems() {
+        for key in &["secret", "username", "password"] {
+            let mut settings = testing::settings(&[]);
+            let credentials = &mut settings.credentials;
+
+            match *key {
+                "secret" => credentials.secret.clear(),
+                "username" => credentials.username.clear(),
+                _ => credentials.password.clear(),
+            }
+
+            assert_eq!(
+                settings.validate(),
+                vec![format!("`credentials.{}` is empty", key)]
+            );
+        }
+    }
+
+    #[test]
+    fn bad_whitelist_entry_is_a_problem() {
+        let settings = testing::settings(&["rust", "r/rust"]);
+
+        let problems = settings.validate();
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("\"r/rust\""));
+    }
+
+    #[test]
+    fn differently_cased_keys_are_accepted() {
+        let cases = [
+            (
+                "upper",
+                r#"
+                WHITELIST = ["rust"]
+
+                [credentials]
+                CLIENT_ID = "abcdefghijklmn"
+                SECRET = "secret"
+                USERNAME = "someone"
+                PASSWORD = "password"
+                "#,
+            ),
+            (
+                "camel",
+                r#"
+                whitelistComments = ["rust"]
+
+                [credentials]
+                clientId = "abcdefghijklmn"
+                secret = "secret"
+                user-name = "someone"
+                Password = "password"
+                "#,
+            ),
+        ];
+
+        for (name, contents) in &cases {
+            let settings = from_toml(name, contents).unwrap();
+
+            assert_eq!(settings.credentials.client_id, "abcdefghijklmn");
+            assert_eq!(settings.credentials.secret, "secret");
+            assert_eq!(settings.credentials.username, "someone");
+            assert_eq!(settings.credentials.password, "password");
+            assert!(settings.comment_whitelist().contains("rust"));
+        }
+    }
+
+    #[test]
+    fn missing_credentials_are_listed_with_the_accepted_keys() {
+        let err = from_toml(
+            "missing",
+            r#"
+            [credentials]
+            clientId = "abcdefghijklmn"
+            secret = "secret"
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "missing username, password under [credentials]. The accepted \
+            keys are client_id, secret, username, password"
+        );
+    }
 }
@@ -0,0 +1,31 @@
+//! What is remembered between runs.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// The contents of the state file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct State {
+    /// When the last successful run started, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub last_run: Option<f64>,
+}
+
+impl State {
+    /// Reads the state at `path`. A missing file is an empty state, as before
+    /// the first run.
+    pub(crate) fn load(path: &Path) -> io::Result<State> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(State::default())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
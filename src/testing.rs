@@ -0,0 +1,682 @@
+//! Fakes shared by the tests.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::app::{AppImpl, Concurrency, Params, RegredditParams};
+use crate::client::{self, Client};
+use crate::error::{Error, ErrorKind, Result};
+use crate::reddit;
+use crate::settings::{Credentials, Settings};
+
+/// A `Client` serving canned listings and recording every call, so tests can
+/// run the app without Reddit.
+#[derive(Clone, Default)]
+pub(crate) struct MockClient {
+    pub state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+pub(crate) struct MockState {
+    /// The pages of each listing, served in order.
+    pub comments: Vec<Vec<reddit::Object>>,
+    pub posts: Vec<Vec<reddit::Object>>,
+    pub saved: Vec<Vec<reddit::Object>>,
+    /// Items that can be looked up by fullname through `get_info`.
+    pub info: Vec<reddit::Object>,
+    /// The top posts of each subreddit.
+    pub top: HashMap<String, Vec<reddit::Object>>,
+    pub over_18: bool,
+    /// The preferences other than over_18.
+    pub other_prefs: HashMap<String, serde_json::Value>,
+    /// How many more times each method fails with a network error before it
+    /// succeeds, by method name.
+    pub failures: HashMap<&'static str, u32>,
+    /// How many authentications succeed before every later one fails.
+    pub successful_auths: Option<u32>,
+    /// Access tokens writes are rejected with, as if they had expired. The
+    /// `n`th authentication hands out `token-{n}`.
+    pub expired_tokens: HashSet<String>,
+    /// Subreddits submitting to fails in.
+    pub failing_subreddits: HashSet<String>,
+    /// Every call made, as the method name and its main argument.
+    pub calls: Vec<(&'static str, String)>,
+    pub deleted: Vec<String>,
+    pub submitted: Vec<reddit::Post>,
+    pub set_prefs: Vec<serde_json::Value>,
+    /// How long each delete takes.
+    pub delete_delay: Duration,
+    /// How many deletes are in flight, and the most there ever were.
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    /// The address of every client instance deletes went through.
+    pub delete_clients: HashSet<usize>,
+}
+
+impl MockClient {
+    pub(crate) fn new(state: MockState) -> Self {
+        MockClient {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// The argument of every call to `method`, in order.
+    pub(crate) fn calls(&self, method: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .iter()
+            .filter(|(called, _)| *called == method)
+            .map(|(_, arg)| arg.clone())
+            .collect()
+    }
+
+    pub(crate) fn deleted(&self) -> Vec<String> {
+        self.state.lock().unwrap().deleted.clone()
+    }
+
+    /// Records a call and fails it if failures are left for `method`.
+    fn call(&self, method: &'static str, arg: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        state.calls.push((method, arg.to_string()));
+
+        match state.failures.get_mut(method) {
+            Some(failures) if *failures > 0 => {
+                *failures -= 1;
+                Err(Error::new(
+                    ErrorKind::Network,
+                    format!("{} failed", method),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_token(&self, access_token: &str) -> Result<()> {
+        if self
+            .state
+            .lock()
+            .unwrap()
+            .expired_tokens
+            .contains(access_token)
+        {
+            return Err(Error::from(ErrorKind::Authentication));
+        }
+
+        Ok(())
+    }
+
+    fn page(
+        &self,
+        method: &'static str,
+        pages: fn(&MockState) -> &Vec<Vec<reddit::Object>>,
+        listing_control: &reddit::ListingControl,
+    ) -> Result<reddit::Object> {
+        let after = listing_control.after.as_deref().unwrap_or_default();
+
+        self.call(method, after)?;
+
+        let state = self.state.lock().unwrap();
+        let pages = pages(&state);
+        let index = match listing_control.after {
+            None => 0,
+            Some(ref after) => pages
+                .iter()
+                .position(|page| cursor(page).as_deref() == Some(after))
+                .map_or(pages.len(), |i| i + 1),
+        };
+        let children = pages.get(index).cloned().unwrap_or_default();
+        let after = if index + 1 < pages.len() {
+            cursor(&children)
+        } else {
+            None
+        };
+
+        Ok(reddit::Object::Listing {
+            modhash: None,
+            dist: children.len() as u64,
+            after,
+            before: None,
+            children,
+        })
+    }
+}
+
+/// The `after` cursor Reddit gives for a page: the fullname of its last item,
+/// skipping "load more" stubs.
+fn cursor(page: &[reddit::Object]) -> Option<String> {
+    page.iter()
+        .rev()
+        .find_map(|item| item.name())
+        .map(|name| name.to_string())
+}
+
+#[async_trait]
+impl Client for MockClient {
+    async fn approve(
+        &self,
+        p: &client::ApproveParams<'_>,
+    ) -> Result<client::ApproveResult> {
+        self.call("approve", p.id)?;
+        self.check_token(p.access_token)?;
+
+        Ok(client::ApproveResult {})
+    }
+
+    async fn basic_auth(
+        &self,
+        _p: &client::BasicAuthParams<'_>,
+    ) -> Result<client::BasicAuthResult> {
+        self.call("basic_auth", "")?;
+
+        let state = self.state.lock().unwrap();
+        let auths = state.calls.iter().filter(|c| c.0 == "basic_auth").count();
+
+        if let Some(successful) = state.successful_auths {
+            if auths > successful as usize {
+                return Err(Error::from(ErrorKind::Authentication));
+            }
+        }
+
+        Ok(client::BasicAuthResult {
+            access_token: format!("token-{}", auths),
+        })
+    }
+
+    async fn delete_link(
+        &self,
+        p: &client::DeleteLinkParams<'_>,
+    ) -> Result<client::DeleteLinkResult> {
+        self.call("delete_link", p.id)?;
+        self.check_token(p.access_token)?;
+
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+
+            state.in_flight += 1;
+            state.max_in_flight = state.max_in_flight.max(state.in_flight);
+            state.delete_clients.insert(self as *const Self as usize);
+            state.delete_delay
+        };
+
+        tokio::time::delay_for(delay).await;
+
+        let mut state = self.state.lock().unwrap();
+
+        state.in_flight -= 1;
+        state.deleted.push(p.id.to_string());
+
+        Ok(client::DeleteLinkResult {})
+    }
+
+    async fn get_comments(
+        &self,
+        p: &client::GetCommentsParams<'_>,
+    ) -> Result<client::GetCommentsResult> {
+        Ok(client::GetCommentsResult {
+            response: self.page(
+                "get_comments",
+                |state| &state.comments,
+                p.listing_control,
+            )?,
+        })
+    }
+
+    async fn get_info(
+        &self,
+        p: &client::GetInfoParams<'_>,
+    ) -> Result<client::GetInfoResult> {
+        self.call("get_info", &p.ids.join(","))?;
+
+        let state = self.state.lock().unwrap();
+        let children = state
+            .info
+            .iter()
+            .filter(|item| {
+                item.name()
+                    .map_or(false, |name| p.ids.iter().any(|id| id == name))
+            })
+            .cloned()
+            .collect();
+
+        Ok(client::GetInfoResult {
+            response: listing(children),
+        })
+    }
+
+    async fn get_posts(
+        &self,
+        p: &client::GetPostsParams<'_>,
+    ) -> Result<client::GetPostsResult> {
+        Ok(client::GetPostsResult {
+            response: self.page(
+                "get_posts",
+                |state| &state.posts,
+                p.listing_control,
+            )?,
+        })
+    }
+
+    async fn get_prefs(
+        &self,
+        _p: &client::GetPrefsParams<'_>,
+    ) -> Result<client::GetPrefsResult> {
+        self.call("get_prefs", "")?;
+
+        let state = self.state.lock().unwrap();
+
+        Ok(client::GetPrefsResult {
+            prefs: reddit::Prefs {
+                over_18: state.over_18,
+                other: state.other_prefs.clone(),
+            },
+        })
+    }
+
+    async fn get_saved(
+        &self,
+        p: &client::GetSavedParams<'_>,
+    ) -> Result<client::GetSavedResult> {
+        Ok(client::GetSavedResult {
+            response: self.page(
+                "get_saved",
+                |state| &state.saved,
+                p.listing_control,
+            )?,
+        })
+    }
+
+    async fn get_subreddit_top(
+        &self,
+        p: &client::GetSubredditTopParams<'_>,
+    ) -> Result<client::GetSubredditTopResult> {
+        self.call("get_subreddit_top", p.subreddit)?;
+
+        let top = self.state.lock().unwrap().top.get(p.subreddit).cloned();
+
+        Ok(client::GetSubredditTopResult {
+            response: listing(top.unwrap_or_default()),
+        })
+    }
+
+    async fn remove(
+        &self,
+        p: &client::RemoveParams<'_>,
+    ) -> Result<client::RemoveResult> {
+        self.call("remove", p.id)?;
+        self.check_token(p.access_token)?;
+
+        Ok(client::RemoveResult {})
+    }
+
+    async fn set_prefs(
+        &self,
+        p: &client::SetPrefsParams<'_>,
+    ) -> Result<client::SetPrefsResult> {
+        self.call("set_prefs", "")?;
+
+        let prefs = serde_json::to_value(p.prefs).unwrap();
+        let mut state = self.state.lock().unwrap();
+
+        state.over_18 = p.prefs.over_18;
+        state.set_prefs.push(prefs);
+
+        Ok(client::SetPrefsResult {})
+    }
+
+    async fn submit(
+        &self,
+        p: &client::SubmitParams<'_>,
+    ) -> Result<client::SubmitResult> {
+        let subreddit = match p.post {
+            reddit::Post::Link { ref subreddit, .. }
+            | reddit::Post::SelfPost { ref subreddit, .. }
+            | reddit::Post::Video { ref subreddit, .. } => subreddit.clone(),
+        };
+
+        self.call("submit", &subreddit)?;
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.failing_subreddits.contains(&subreddit) {
+            return Err(Error::new(ErrorKind::Reddit, "submit unsuccessful"));
+        }
+
+        state.submitted.push(p.post.clone());
+
+        Ok(client::SubmitResult {
+            name: Some(format!("t3_{}", subreddit.to_lowercase())),
+        })
+    }
+
+    async fn unsave(
+        &self,
+        p: &client::UnsaveParams<'_>,
+    ) -> Result<client::UnsaveResult> {
+        self.call("unsave", p.id)?;
+        self.check_token(p.access_token)?;
+
+        Ok(client::UnsaveResult {})
+    }
+}
+
+/// An app backed by `client`.
+pub(crate) fn app(client: &MockClient) -> AppImpl {
+    AppImpl::new(Params {
+        client: Box::new(client.clone()),
+    })
+}
+
+pub(crate) fn settings(whitelist: &[&str]) -> Settings {
+    Settings {
+        credentials: Credentials {
+            client_id: "abcdefghijklmn".to_string(),
+            secret: "secret".to_string(),
+            username: "someone".to_string(),
+            password: "hunter2".to_string(),
+        },
+        whitelist: whitelist.iter().map(|s| s.to_string()).collect(),
+        whitelist_comments: Vec::new(),
+        whitelist_posts: Vec::new(),
+    }
+}
+
+/// Parameters for a run that deletes everything without asking.
+pub(crate) fn params(settings: &Settings) -> RegredditParams<'_> {
+    RegredditParams {
+        settings,
+        listing_retries: 0,
+        delete_retries: 0,
+        sample: None,
+        seed: None,
+        set_over_18: false,
+        ignore_whitelist: false,
+        strict_subreddit_match: false,
+        only_inaccessible: false,
+        randomize_order: false,
+        keep_if_trending: false,
+        comment_depth: None,
+        created_after: None,
+        score_min: None,
+        score_max: None,
+        max_listing_pages: None,
+        listing_params: HashMap::new(),
+        thorough: false,
+        concurrency: Concurrency::Fixed(4),
+        max_auth_failures: 3,
+        mod_action: None,
+        backup_prefs: None,
+        unsave: false,
+        unsave_others_only: false,
+        confirm: None,
+        snapshot: None,
+    }
+}
+
+pub(crate) fn listing(children: Vec<reddit::Object>) -> reddit::Object {
+    reddit::Object::Listing {
+        modhash: None,
+        dist: children.len() as u64,
+        after: None,
+        before: None,
+        children,
+    }
+}
+
+/// A top-level comment `t1_{id}` in `subreddit`.
+pub(crate) fn comment(id: &str, subreddit: &str) -> reddit::Object {
+    reddit::Object::Comment {
+        author: "someone".to_string(),
+        link_title: format!("Post in r/{}", subreddit),
+        link_id: "t3_parent".to_string(),
+        parent_id: "t3_parent".to_string(),
+        name: format!("t1_{}", id),
+        subreddit: subreddit.to_string(),
+        created_utc: 1_500_000_000.0,
+        score: 1,
+        body: format!("Comment {}", id),
+        sr_detail: None,
+    }
+}
+
+/// A self-post `t3_{id}` in `subreddit`.
+pub(crate) fn post(id: &str, subreddit: &str) -> reddit::Object {
+    reddit::Object::Link {
+        author: "someone".to_string(),
+        subreddit: subreddit.to_string(),
+        title: format!("Post {}", id),
+        name: format!("t3_{}", id),
+        created_utc: 1_500_000_000.0,
+        score: 1,
+        is_self: true,
+        url: format!("https://www.reddit.com/r/{}/comments/{}/", subreddit, id),
+        selftext: format!("Text {}", id),
+        sr_detail: None,
+    }
+}
+
+/// `item` as if it had been created at `created`.
+pub(crate) fn created_at(
+    mut item: reddit::Object,
+    created: f64,
+) -> reddit::Object {
+    match item {
+        reddit::Object::Comment {
+            ref mut created_utc,
+            ..
+        }
+        | reddit::Object::Link {
+            ref mut created_utc,
+            ..
+        } => *created_utc = created,
+        _ => {}
+    }
+
+    item
+}
+
+/// `item` with a score of `score`.
+pub(crate) fn scored(mut item: reddit::Object, score: i64) -> reddit::Object {
+    match item {
+        reddit::Object::Comment {
+            score: ref mut item_score,
+            ..
+        }
+        | reddit::Object::Link {
+            score: ref mut item_score,
+            ..
+        } => *item_score = score,
+        _ => {}
+    }
+
+    item
+}
+
+/// `item` written by `name`.
+pub(crate) fn authored_by(
+    mut item: reddit::Object,
+    name: &str,
+) -> reddit::Object {
+    match item {
+        reddit::Object::Comment { ref mut author, .. }
+        | reddit::Object::Link { ref mut author, .. } => {
+            *author = name.to_string()
+        }
+        _ => {}
+    }
+
+    item
+}
+
+/// A path in the temporary directory unique to this process.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "regreddit-test-{}-{}",
+        std::process::id(),
+        name
+    ))
+}
+
+/// An HTTP server on localhost answering each connection with the next canned
+/// response, for testing what is actually sent over the wire.
+pub(crate) struct Server {
+    listener: TcpListener,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+/// A request received by a `Server`.
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+    pub method: String,
+    /// The path with the query string.
+    pub path: String,
+    /// The headers, with lowercase names.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl Server {
+    pub(crate) fn new() -> Self {
+        Server {
+            listener: TcpListener::bind("127.0.0.1:0").unwrap(),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub(crate) fn url(&self) -> String {
+        format!("http://{}", self.listener.local_addr().unwrap())
+    }
+
+    /// Answers the next connections with `responses`, one each.
+    pub(crate) fn serve(&self, responses: Vec<Response>) {
+        let listener = self.listener.try_clone().unwrap();
+        let requests = self.requests.clone();
+
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut BufReader::new(
+                    stream.try_clone().unwrap(),
+                ));
+                let mut head = format!(
+                    "HTTP/1.1 {} Canned\r\nConnection: close\r\n\
+                    Content-Length: {}\r\n",
+                    response.status,
+                    response.body.len()
+                );
+
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+
+                requests.lock().unwrap().push(request);
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(b"\r\n").unwrap();
+                stream.write_all(response.body.as_bytes()).unwrap();
+            }
+        });
+    }
+
+    /// The requests received so far.
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Request {
+    /// The body decoded as a URL-encoded form.
+    pub(crate) fn form(&self) -> HashMap<String, String> {
+        url::form_urlencoded::parse(&self.body)
+            .into_owned()
+            .collect()
+    }
+
+    pub(crate) fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+impl Response {
+    pub(crate) fn ok(body: &str) -> Self {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Request {
+    let mut line = String::new();
+
+    reader.read_line(&mut line).unwrap();
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut headers = HashMap::new();
+
+    loop {
+        let mut line = String::new();
+
+        reader.read_line(&mut line).unwrap();
+
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(
+                    name.to_ascii_lowercase(),
+                    value.trim().to_string(),
+                );
+            }
+            None => break,
+        }
+    }
+
+    let mut body = Vec::new();
+
+    if let Some(len) = headers.get("content-length") {
+        body.resize(len.parse().unwrap(), 0);
+        reader.read_exact(&mut body).unwrap();
+    } else if headers.get("transfer-encoding").map(String::as_str)
+        == Some("chunked")
+    {
+        loop {
+            let mut size = String::new();
+
+            reader.read_line(&mut size).unwrap();
+
+            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+
+            reader.read_exact(&mut chunk).unwrap();
+
+            if size == 0 {
+                break;
+            }
+
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    Request {
+        method,
+        path,
+        headers,
+        body,
+    }
+}